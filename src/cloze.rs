@@ -0,0 +1,152 @@
+/// Cloze deletions written as `{{c1::answer}}` or `{{c1::answer::hint}}`.
+/// A note with several cloze numbers becomes one card per number.
+struct Cloze<'a> {
+    start: usize,
+    end: usize,
+    index: u32,
+    answer: &'a str,
+    hint: Option<&'a str>,
+}
+
+fn parse(text: &str) -> Vec<Cloze<'_>> {
+    let mut clozes = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = text[offset..].find("{{c") {
+        let start = offset + found;
+        let rest = &text[start + 3..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let parsed = rest[digits..]
+            .strip_prefix("::")
+            .and_then(|body| body.find("}}").map(|close| &body[..close]))
+            // An unclosed deletion must not swallow the next one.
+            .filter(|body| !body.contains("{{"))
+            .and_then(|body| Some((rest[..digits].parse().ok()?, body)));
+        match parsed {
+            Some((index, body)) => {
+                let end = start + 3 + digits + 2 + body.len() + 2;
+                let (answer, hint) = match body.split_once("::") {
+                    Some((answer, hint)) => (answer, Some(hint)),
+                    None => (body, None),
+                };
+                clozes.push(Cloze {
+                    start,
+                    end,
+                    index,
+                    answer,
+                    hint,
+                });
+                offset = end;
+            }
+            None => offset = start + 3,
+        }
+    }
+    clozes
+}
+
+/// The distinct cloze numbers in a note, in ascending order.
+pub fn indices(text: &str) -> Vec<u32> {
+    let mut indices: Vec<u32> = parse(text).iter().map(|c| c.index).collect();
+    indices.sort();
+    indices.dedup();
+    indices
+}
+
+/// Renders the card for one cloze number: its deletions are blanked out in
+/// the question and joined as the answer, while the others are shown as
+/// plain text.
+pub fn render(text: &str, index: u32) -> (String, String) {
+    let mut question = String::new();
+    let mut answers = Vec::new();
+    let mut last = 0;
+    for cloze in parse(text) {
+        question.push_str(&text[last..cloze.start]);
+        if cloze.index == index {
+            match cloze.hint {
+                Some(hint) => question.push_str(&format!("[{}]", hint)),
+                None => question.push_str("[...]"),
+            }
+            answers.push(cloze.answer);
+        } else {
+            question.push_str(cloze.answer);
+        }
+        last = cloze.end;
+    }
+    question.push_str(&text[last..]);
+    (question, answers.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_are_distinct_and_sorted() {
+        let text = "{{c2::Paris}} is the capital of {{c1::France}}, on the {{c2::Seine}}";
+        assert_eq!(indices(text), vec![1, 2]);
+    }
+
+    #[test]
+    fn render_blanks_only_the_given_index() {
+        let text = "{{c2::Paris}} is the capital of {{c1::France}}, on the {{c2::Seine}}";
+        assert_eq!(
+            render(text, 1),
+            (
+                "Paris is the capital of [...], on the Seine".to_string(),
+                "France".to_string()
+            )
+        );
+        assert_eq!(
+            render(text, 2),
+            (
+                "[...] is the capital of France, on the [...]".to_string(),
+                "Paris, Seine".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn hint_replaces_the_blank() {
+        let text = "The {{c1::mitochondria::organelle}} is the powerhouse of the {{c2::cell}}";
+        assert_eq!(
+            render(text, 1),
+            (
+                "The [organelle] is the powerhouse of the cell".to_string(),
+                "mitochondria".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn multi_byte_text_around_deletions() {
+        let text = "Ça {{c1::été}} très {{c12::beau::adj}} — fin";
+        assert_eq!(indices(text), vec![1, 12]);
+        assert_eq!(
+            render(text, 12),
+            ("Ça été très [adj] — fin".to_string(), "beau".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_deletions_are_left_as_text() {
+        for text in [
+            "trailing {{c",
+            "no number {{c::x}}",
+            "not a digit {{cx::x}}",
+            "single colon {{c1:x}}",
+            "unclosed {{c1::x",
+        ] {
+            assert!(indices(text).is_empty(), "{}", text);
+            assert_eq!(render(text, 1), (text.to_string(), String::new()));
+        }
+    }
+
+    #[test]
+    fn unclosed_deletion_does_not_swallow_the_next() {
+        let text = "{{c1::oops and {{c2::fine}}";
+        assert_eq!(indices(text), vec![2]);
+        assert_eq!(
+            render(text, 2),
+            ("{{c1::oops and [...]".to_string(), "fine".to_string())
+        );
+    }
+}
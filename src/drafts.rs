@@ -0,0 +1,95 @@
+use crate::{prompt, usage_error, SpacedRepetitionManager};
+use std::io;
+
+fn draft_questions(manager: &SpacedRepetitionManager, deck: Option<&str>) -> Vec<String> {
+    let mut questions: Vec<String> = manager
+        .flashcards
+        .values()
+        .filter(|f| f.draft && deck.is_none_or(|d| d == f.deck))
+        .map(|f| f.question.clone())
+        .collect();
+    questions.sort();
+    questions
+}
+
+fn not_a_draft(question: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no draft with question: {}", question),
+    )
+}
+
+fn prompt_with_default(label: &str, current: &str) -> io::Result<String> {
+    let input = prompt(&format!("Enter the {}:(default: {})", label, current))?;
+    Ok(if input.is_empty() {
+        current.to_string()
+    } else {
+        input
+    })
+}
+
+/// Walks through a draft's fields, keeping the current value of any field
+/// left empty.
+fn edit(manager: &mut SpacedRepetitionManager, question: &str) -> io::Result<()> {
+    let mut flashcard = match manager.flashcards.get(question) {
+        Some(flashcard) if flashcard.draft => flashcard.clone(),
+        _ => return Err(not_a_draft(question)),
+    };
+    println!("Answer: {}", flashcard.answer);
+    println!("Hint: {}", flashcard.guidance);
+    flashcard.question = prompt_with_default("question", &flashcard.question)?;
+    flashcard.answer = prompt_with_default("answer", &flashcard.answer)?;
+    flashcard.guidance = prompt_with_default("hint or guidance", &flashcard.guidance)?;
+    flashcard.deck = prompt_with_default("deck", &flashcard.deck)?;
+    if prompt("Promote to a regular card? (y/n):")?.to_lowercase() == "y" {
+        flashcard.draft = false;
+    }
+
+    manager.flashcards.remove(question);
+    manager.insert_flashcard(flashcard);
+    manager.save()
+}
+
+/// Moves drafts into the scheduler as new cards.
+fn promote(manager: &mut SpacedRepetitionManager, questions: &[String]) -> io::Result<()> {
+    for question in questions {
+        match manager.flashcards.get_mut(question) {
+            Some(flashcard) if flashcard.draft => flashcard.draft = false,
+            _ => return Err(not_a_draft(question)),
+        }
+    }
+    manager.save()?;
+    println!("Promoted {} drafts.", questions.len());
+    Ok(())
+}
+
+pub fn run_drafts_command(
+    manager: &mut SpacedRepetitionManager,
+    args: &[String],
+) -> io::Result<()> {
+    match args {
+        [] => {
+            for question in draft_questions(manager, None) {
+                println!("[{}] {}", manager.flashcards[&question].deck, question);
+            }
+        }
+        [command, question] if command == "edit" => edit(manager, question)?,
+        [command, flag] if command == "promote" && flag == "--all" => {
+            promote(manager, &draft_questions(manager, None))?
+        }
+        [command, flag, deck] if command == "promote" && flag == "--deck" => {
+            promote(manager, &draft_questions(manager, Some(deck)))?
+        }
+        [command, question] if command == "promote" => promote(manager, std::slice::from_ref(question))?,
+        [command, question] if command == "delete" => {
+            match manager.flashcards.get(question) {
+                Some(flashcard) if flashcard.draft => {}
+                _ => return Err(not_a_draft(question)),
+            }
+            manager.flashcards.remove(question);
+            manager.save()?;
+        }
+        _ => return Err(usage_error("drafts", "invalid drafts command")),
+    }
+    Ok(())
+}
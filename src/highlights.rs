@@ -0,0 +1,253 @@
+use crate::{prompt, Flashcard, SpacedRepetitionManager};
+use serde_json::Value;
+use std::fs;
+use std::io;
+
+const QUESTION_WORDS: usize = 8;
+
+/// A highlight from a reading tool export, before it becomes a card.
+struct Highlight {
+    text: String,
+    note: String,
+    source: String,
+}
+
+impl Highlight {
+    /// Uses the reader's own note as the question when there is one,
+    /// otherwise asks to complete the highlight from its opening words
+    /// (never more than half of them, so the question leaves something to
+    /// recall). A highlight of a single word has no opening to show, so
+    /// there is no question to generate.
+    fn question(&self) -> Option<String> {
+        if !self.note.is_empty() {
+            return Some(self.note.clone());
+        }
+        let words: Vec<&str> = self.text.split_whitespace().collect();
+        let shown = (words.len() / 2).min(QUESTION_WORDS);
+        if shown == 0 {
+            return None;
+        }
+        let opening = words[..shown].join(" ");
+        Some(if self.source.is_empty() {
+            format!("Complete the highlight: \"{} …\"", opening)
+        } else {
+            format!(
+                "Complete the highlight from {}: \"{} …\"",
+                self.source, opening
+            )
+        })
+    }
+}
+
+/// Parses a Kindle `My Clippings.txt`. Entries are separated by `==========`
+/// lines; notes are attached to the highlight just before them.
+fn parse_kindle(data: &str) -> Vec<Highlight> {
+    let mut highlights: Vec<Highlight> = Vec::new();
+    for entry in data.split("==========") {
+        let mut lines = entry.trim_start_matches('\u{feff}').trim().lines();
+        let (title, meta) = match (lines.next(), lines.next()) {
+            (Some(title), Some(meta)) => (title.trim(), meta.trim()),
+            _ => continue,
+        };
+        let text = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+        if text.is_empty() {
+            continue;
+        }
+        if meta.starts_with("- Your Highlight") {
+            highlights.push(Highlight {
+                text,
+                note: String::new(),
+                source: title.to_string(),
+            });
+        } else if meta.starts_with("- Your Note") {
+            if let Some(last) = highlights.last_mut().filter(|h| h.source == title) {
+                last.note = text;
+            }
+        }
+    }
+    highlights
+}
+
+/// Parses a Readwise CSV export, which has `Highlight`, `Note`,
+/// `Book Title` and `Book Author` columns.
+fn parse_readwise(data: &str) -> io::Result<Vec<Highlight>> {
+    let mut reader = csv::Reader::from_reader(data.as_bytes());
+    let headers = reader.headers().map_err(io::Error::other)?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let text_column = column("Highlight")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Highlight column"))?;
+    let (note_column, title_column, author_column) =
+        (column("Note"), column("Book Title"), column("Book Author"));
+
+    let mut highlights = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(io::Error::other)?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .unwrap_or("")
+                .trim()
+                .to_string()
+        };
+        let text = field(Some(text_column));
+        if text.is_empty() {
+            continue;
+        }
+        let (title, author) = (field(title_column), field(author_column));
+        let source = if author.is_empty() {
+            title
+        } else {
+            format!("{} ({})", title, author)
+        };
+        highlights.push(Highlight {
+            text,
+            note: field(note_column),
+            source,
+        });
+    }
+    Ok(highlights)
+}
+
+/// Parses a Hypothesis JSON export: either a bare array of annotations or an
+/// object with an `annotations` array.
+fn parse_hypothesis(data: &str) -> io::Result<Vec<Highlight>> {
+    let value: Value = serde_json::from_str(data)?;
+    let annotations = match &value {
+        Value::Array(annotations) => annotations,
+        Value::Object(object) => match object.get("annotations") {
+            Some(Value::Array(annotations)) => annotations,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing annotations",
+                ))
+            }
+        },
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected JSON",
+            ))
+        }
+    };
+
+    let mut highlights = Vec::new();
+    for annotation in annotations {
+        let text = annotation["target"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|target| target["selector"].as_array().into_iter().flatten())
+            .find(|selector| selector["type"] == "TextQuoteSelector")
+            .and_then(|selector| selector["exact"].as_str())
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        if text.is_empty() {
+            continue;
+        }
+        let source = annotation["document"]["title"][0]
+            .as_str()
+            .or_else(|| annotation["uri"].as_str())
+            .unwrap_or("")
+            .to_string();
+        highlights.push(Highlight {
+            text,
+            note: annotation["text"].as_str().unwrap_or("").trim().to_string(),
+            source,
+        });
+    }
+    Ok(highlights)
+}
+
+/// Turns every highlight in an export into a draft card. With `ask` set, the
+/// generated question is shown and can be replaced before the draft is saved.
+pub fn import(
+    manager: &mut SpacedRepetitionManager,
+    format: &str,
+    path: &str,
+    deck: &str,
+    ask: bool,
+) -> io::Result<()> {
+    let data = fs::read_to_string(path)?;
+    let highlights = match format {
+        "kindle" => parse_kindle(&data),
+        "readwise" => parse_readwise(&data)?,
+        "hypothesis" => parse_hypothesis(&data)?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown highlights format: {}", format),
+            ))
+        }
+    };
+
+    let mut count = 0;
+    for highlight in highlights {
+        let question = match highlight.question() {
+            Some(question) if ask => {
+                println!("Highlight: {}", highlight.text);
+                let typed = prompt(&format!("Enter the question:(default: {})", question))?;
+                if typed.is_empty() {
+                    question
+                } else {
+                    typed
+                }
+            }
+            Some(question) => question,
+            None => {
+                println!("Highlight: {}", highlight.text);
+                let typed = prompt("Enter the question (leave empty to skip this highlight):")?;
+                if typed.is_empty() {
+                    continue;
+                }
+                typed
+            }
+        };
+        let mut flashcard =
+            Flashcard::new(question, highlight.text, highlight.source, deck.to_string());
+        flashcard.draft = true;
+        manager.insert_flashcard(flashcard);
+        count += 1;
+    }
+    manager.save()?;
+    println!("Imported {} highlights as drafts.", count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(text: &str) -> Highlight {
+        Highlight {
+            text: text.to_string(),
+            note: String::new(),
+            source: String::new(),
+        }
+    }
+
+    #[test]
+    fn question_shows_at_most_half_of_the_words() {
+        assert_eq!(
+            highlight("two words").question().as_deref(),
+            Some("Complete the highlight: \"two …\"")
+        );
+        assert_eq!(
+            highlight("one two three four five").question().as_deref(),
+            Some("Complete the highlight: \"one two …\"")
+        );
+    }
+
+    #[test]
+    fn single_word_highlight_has_no_generated_question() {
+        assert_eq!(highlight("ephemeral").question(), None);
+    }
+
+    #[test]
+    fn note_is_used_as_the_question() {
+        let mut highlight = highlight("ephemeral");
+        highlight.note = "Word for short-lived?".to_string();
+        assert_eq!(highlight.question().as_deref(), Some("Word for short-lived?"));
+    }
+}
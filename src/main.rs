@@ -1,8 +1,27 @@
+mod cloze;
+mod drafts;
+mod highlights;
+mod mistakes;
+mod plan;
+mod server;
+mod snapshot;
+mod stats;
+mod window;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use icu_collator::options::CollatorOptions;
+use icu_collator::{Collator, CollatorBorrowed, CollatorPreferences};
+use icu_locale_core::Locale;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use snapshot::SnapshotStore;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use window::StudyWindow;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Flashcard {
@@ -13,14 +32,42 @@ struct Flashcard {
     repetitions: u32,
     ease_factor: f32,
     next_review: u64,
+    #[serde(default = "default_deck")]
+    deck: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    windows: Vec<StudyWindow>,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    draft: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    cloze: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+const DEFAULT_DECK: &str = "default";
+
+fn default_deck() -> String {
+    DEFAULT_DECK.to_string()
 }
 
 impl Flashcard {
-    fn new(question: String, answer: String, guidance: String) -> Self {
+    fn new(question: String, answer: String, guidance: String, deck: String) -> Self {
         Flashcard {
             question,
             answer,
             guidance,
+            deck,
+            windows: Vec::new(),
+            created_at: now_secs().unwrap_or(0),
+            draft: false,
+            note: None,
+            cloze: 0,
             interval: 0,
             repetitions: 0,
             ease_factor: 2.5,
@@ -28,6 +75,10 @@ impl Flashcard {
         }
     }
 
+    fn is_new(&self) -> bool {
+        self.next_review == 0
+    }
+
     fn update(&mut self, performance: u32) {
         match performance {
             0 => {
@@ -59,33 +110,311 @@ impl Flashcard {
     }
 }
 
+const PASSING_PERFORMANCE: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Confidence {
+    Sure,
+    Unsure,
+}
+
+impl Confidence {
+    fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "s" | "sure" => Some(Confidence::Sure),
+            "u" | "unsure" => Some(Confidence::Unsure),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReviewRecord {
+    question: String,
+    reviewed_at: u64,
+    performance: u32,
+    interval: u32,
+    #[serde(default)]
+    confidence: Option<Confidence>,
+    #[serde(default)]
+    new_card: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    typed_answer: Option<String>,
+    #[serde(default)]
+    duration_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    ask_confidence: bool,
+    deck_languages: HashMap<String, String>,
+    new_cards_per_day: Option<usize>,
+    adaptive_new_cards: bool,
+    backlog_threshold: usize,
+    deck_windows: HashMap<String, Vec<StudyWindow>>,
+    api_tokens: Vec<server::ApiToken>,
+    capture_as_draft: bool,
+    note_review_decks: HashSet<String>,
+    deck_exams: HashMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ask_confidence: false,
+            deck_languages: HashMap::new(),
+            new_cards_per_day: None,
+            adaptive_new_cards: false,
+            backlog_threshold: 50,
+            deck_windows: HashMap::new(),
+            api_tokens: Vec::new(),
+            capture_as_draft: false,
+            note_review_decks: HashSet::new(),
+            deck_exams: HashMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Windows set on the card itself take precedence over its deck's.
+    fn study_windows<'a>(&'a self, flashcard: &'a Flashcard) -> &'a [StudyWindow] {
+        if !flashcard.windows.is_empty() {
+            return &flashcard.windows;
+        }
+        self.deck_windows
+            .get(&flashcard.deck)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Number of new cards to introduce per day given the current review
+    /// backlog. In adaptive mode the limit shrinks linearly once the backlog
+    /// passes the threshold, reaching zero at twice the threshold.
+    fn new_card_limit(&self, backlog: usize) -> Option<usize> {
+        let limit = self.new_cards_per_day?;
+        if !self.adaptive_new_cards || backlog <= self.backlog_threshold {
+            return Some(limit);
+        }
+        let excess = backlog - self.backlog_threshold;
+        Some(
+            (limit * self.backlog_threshold.saturating_sub(excess))
+                .checked_div(self.backlog_threshold)
+                .unwrap_or(0),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Calibration {
+    sure: usize,
+    sure_failed: usize,
+    unsure: usize,
+    unsure_passed: usize,
+}
+
+impl Calibration {
+    const MIN_SAMPLES: usize = 10;
+
+    fn from_log(review_log: &[ReviewRecord]) -> Self {
+        let mut calibration = Calibration::default();
+        for record in review_log {
+            let passed = record.performance >= PASSING_PERFORMANCE;
+            match record.confidence {
+                Some(Confidence::Sure) => {
+                    calibration.sure += 1;
+                    if !passed {
+                        calibration.sure_failed += 1;
+                    }
+                }
+                Some(Confidence::Unsure) => {
+                    calibration.unsure += 1;
+                    if passed {
+                        calibration.unsure_passed += 1;
+                    }
+                }
+                None => {}
+            }
+        }
+        calibration
+    }
+
+    fn suggestions(&self) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        if self.sure >= Self::MIN_SAMPLES {
+            let rate = percentage(self.sure_failed, self.sure);
+            if rate >= 20 {
+                suggestions.push(format!(
+                    "You say 'sure' but fail {}% of those. Consider grading a little lower when an answer only feels familiar.",
+                    rate
+                ));
+            }
+        }
+        if self.unsure >= Self::MIN_SAMPLES {
+            let rate = percentage(self.unsure_passed, self.unsure);
+            if rate >= 80 {
+                suggestions.push(format!(
+                    "You say 'unsure' but pass {}% of those. You may know these better than you think.",
+                    rate
+                ));
+            }
+        }
+        suggestions
+    }
+
+    fn print(&self) {
+        if self.sure + self.unsure == 0 {
+            println!("No confidence ratings recorded yet.");
+            return;
+        }
+        println!(
+            "Sure: {} reviews, {}% failed",
+            self.sure,
+            percentage(self.sure_failed, self.sure)
+        );
+        println!(
+            "Unsure: {} reviews, {}% passed",
+            self.unsure,
+            percentage(self.unsure_passed, self.unsure)
+        );
+        for suggestion in self.suggestions() {
+            println!("{}", suggestion);
+        }
+    }
+}
+
+fn percentage(part: usize, total: usize) -> usize {
+    (part * 100).checked_div(total).unwrap_or(0)
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ReviewEvent<'a> {
+    CardShown {
+        question: &'a str,
+        deck: &'a str,
+        position: usize,
+        total: usize,
+        at: u64,
+    },
+    Graded {
+        question: &'a str,
+        deck: &'a str,
+        performance: u32,
+        interval: u32,
+        next_review: u64,
+        at: u64,
+    },
+    SessionEnded {
+        reviewed: usize,
+        at: u64,
+    },
+}
+
+/// Writes one JSON event per line to the event sink, if any. A sink that
+/// stops accepting writes (e.g. the reader went away) is dropped so the
+/// review session itself carries on.
+fn emit_event(events: &mut Option<Box<dyn Write>>, event: ReviewEvent) {
+    if let Some(sink) = events {
+        let result = serde_json::to_string(&event)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(sink, "{}", line))
+            .and_then(|_| sink.flush());
+        if let Err(e) = result {
+            eprintln!("Stopping event stream: {}", e);
+            *events = None;
+        }
+    }
+}
+
 struct SpacedRepetitionManager {
     flashcards: HashMap<String, Flashcard>,
+    review_log: Vec<ReviewRecord>,
+    settings: Settings,
+    events: Option<Box<dyn Write>>,
     batch_size: usize,
     flashcards_file: String,
+    review_log_file: String,
+    settings_file: String,
 }
 
 impl SpacedRepetitionManager {
-    fn new(batch_size: usize, flashcards_file: String) -> Self {
+    fn new(
+        batch_size: usize,
+        flashcards_file: String,
+        review_log_file: String,
+        settings_file: String,
+    ) -> Self {
         SpacedRepetitionManager {
             flashcards: HashMap::new(),
+            review_log: Vec::new(),
+            settings: Settings::default(),
+            events: None,
             batch_size,
             flashcards_file,
+            review_log_file,
+            settings_file,
+        }
+    }
+
+    fn add_flashcard(
+        &mut self,
+        question: String,
+        answer: String,
+        guidance: String,
+        deck: String,
+        draft: bool,
+    ) {
+        let indices = cloze::indices(&question);
+        if indices.is_empty() {
+            let mut flashcard = Flashcard::new(question, answer, guidance, deck);
+            flashcard.draft = draft;
+            self.insert_flashcard(flashcard);
+            return;
+        }
+
+        // The cloze deletions are the answers, so any answer given with the
+        // note is kept as extra guidance.
+        let guidance = match (guidance.is_empty(), answer.is_empty()) {
+            (_, true) => guidance,
+            (true, false) => answer,
+            (false, false) => format!("{} {}", guidance, answer),
+        };
+        for index in indices {
+            let (cloze_question, cloze_answer) = cloze::render(&question, index);
+            let mut flashcard =
+                Flashcard::new(cloze_question, cloze_answer, guidance.clone(), deck.clone());
+            flashcard.draft = draft;
+            flashcard.note = Some(question.clone());
+            flashcard.cloze = index;
+            self.insert_flashcard(flashcard);
         }
     }
 
-    fn add_flashcard(&mut self, question: String, answer: String, guidance: String) {
-        let mut unique_question = question.clone();
+    /// The cards of a cloze note, in cloze order.
+    fn note_siblings(&self, note: &str) -> Vec<&Flashcard> {
+        let mut siblings: Vec<&Flashcard> = self
+            .flashcards
+            .values()
+            .filter(|f| !f.draft && f.note.as_deref() == Some(note))
+            .collect();
+        siblings.sort_by_key(|f| f.cloze);
+        siblings
+    }
+
+    /// Inserts a card, numbering its question if the question is taken.
+    fn insert_flashcard(&mut self, mut flashcard: Flashcard) {
+        let question = flashcard.question.clone();
         let mut counter = 1;
-        while self.flashcards.contains_key(&unique_question) {
-            unique_question = format!("{} ({})", question, counter);
+        while self.flashcards.contains_key(&flashcard.question) {
+            flashcard.question = format!("{} ({})", question, counter);
             counter += 1;
         }
-        let flashcard = Flashcard::new(unique_question.clone(), answer, guidance);
-        self.flashcards.insert(unique_question, flashcard);
+        self.flashcards.insert(flashcard.question.clone(), flashcard);
     }
 
-    fn batch_add_flashcards(&mut self, file_path: &str) -> io::Result<()> {
+    fn batch_add_flashcards(&mut self, file_path: &str, draft: bool) -> io::Result<()> {
         let file = File::open(file_path)?;
         let reader = BufReader::new(file);
 
@@ -96,11 +425,15 @@ impl SpacedRepetitionManager {
                 continue;
             }
             let parts: Vec<&str> = trimmed_line.split('~').collect();
-            if parts.len() == 3 {
+            if parts.len() == 3 || parts.len() == 4 {
                 let question = parts[0].trim().to_string();
                 let answer = parts[1].trim().to_string();
                 let guidance = parts[2].trim().to_string();
-                self.add_flashcard(question, answer, guidance);
+                let deck = match parts.get(3).map(|d| d.trim()) {
+                    Some(d) if !d.is_empty() => d.to_string(),
+                    _ => default_deck(),
+                };
+                self.add_flashcard(question, answer, guidance, deck, draft);
             }
         }
 
@@ -108,85 +441,522 @@ impl SpacedRepetitionManager {
         Ok(())
     }
 
-    fn review_flashcards(&mut self) -> io::Result<()> {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)
-            .map(|n| n.as_secs())
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "SystemTime error"))?;
+    /// How many new cards may still be introduced today, or `None` if new
+    /// cards are unlimited.
+    fn new_cards_remaining(&self, now: u64) -> io::Result<Option<usize>> {
+        let local_now = Local::now();
+        let backlog = self
+            .flashcards
+            .values()
+            .filter(|f| !f.draft && !f.is_new() && f.next_review <= now)
+            .filter(|f| window::is_open(self.settings.study_windows(f), &local_now))
+            .count();
+        let limit = match self.settings.new_card_limit(backlog) {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+        if Some(limit) != self.settings.new_cards_per_day {
+            println!(
+                "Review backlog is {} cards; introducing at most {} new cards today.",
+                backlog, limit
+            );
+        }
+        let today = start_of_today()?;
+        let introduced = self
+            .review_log
+            .iter()
+            .filter(|r| r.new_card && r.reviewed_at >= today)
+            .count();
+        Ok(Some(limit.saturating_sub(introduced)))
+    }
+
+    /// The questions to review now, in order. Cards of a cloze note are
+    /// reviewed together in decks set to note-level review, but only when
+    /// every card of the note is in its study window and the new ones fit in
+    /// today's limits (or the limits are still untouched); elsewhere only the first due card of a note is shown
+    /// per session so its siblings don't give the answer away.
+    fn review_queue(&self, now: u64) -> io::Result<Vec<String>> {
+        let mut new_cards_remaining = self.new_cards_remaining(now)?;
+        let mut planned_remaining = plan::new_cards_remaining(self)?;
+        let local_now = Local::now();
+        let window_open =
+            |f: &Flashcard| window::is_open(self.settings.study_windows(f), &local_now);
+        // Decks with new cards introduced today; `None` stands for the daily
+        // limit across all decks.
+        let today = start_of_today()?;
+        let mut touched: HashSet<Option<String>> = self
+            .review_log
+            .iter()
+            .filter(|r| r.new_card && r.reviewed_at >= today)
+            .flat_map(|r| {
+                let deck = self.flashcards.get(&r.question).map(|f| f.deck.clone());
+                std::iter::once(None).chain(deck.map(Some))
+            })
+            .collect();
+        // Counts the new cards among `cards` against the daily and planned
+        // limits, all or nothing. A note with more new cards than a limit
+        // allows may still overdraw it while the limit is untouched today,
+        // so that it is not held back forever.
+        let mut take_new = |cards: &[&Flashcard]| {
+            let mut per_deck: HashMap<&str, usize> = HashMap::new();
+            for flashcard in cards.iter().filter(|f| f.is_new()) {
+                *per_deck.entry(&flashcard.deck).or_default() += 1;
+            }
+            let total: usize = per_deck.values().sum();
+            if total == 0 {
+                return true;
+            }
+            let fits = |remaining: usize, count: usize, deck: Option<&str>| {
+                count <= remaining || (remaining > 0 && !touched.contains(&deck.map(str::to_string)))
+            };
+            if !new_cards_remaining.is_none_or(|remaining| fits(remaining, total, None))
+                || per_deck.iter().any(|(deck, count)| {
+                    planned_remaining
+                        .get(*deck)
+                        .is_some_and(|remaining| !fits(*remaining, *count, Some(deck)))
+                })
+            {
+                return false;
+            }
+            if let Some(remaining) = &mut new_cards_remaining {
+                *remaining = remaining.saturating_sub(total);
+            }
+            touched.insert(None);
+            for (deck, count) in per_deck {
+                if let Some(remaining) = planned_remaining.get_mut(deck) {
+                    *remaining = remaining.saturating_sub(count);
+                }
+                touched.insert(Some(deck.to_string()));
+            }
+            true
+        };
 
-        let mut flashcards: Vec<&mut Flashcard> = self.flashcards.values_mut().collect();
-        flashcards.sort_by_key(|f| f.next_review);
+        let mut due: Vec<&Flashcard> = self
+            .flashcards
+            .values()
+            .filter(|f| !f.draft && f.next_review <= now && window_open(f))
+            .collect();
+        due.sort_by_key(|f| f.next_review);
 
-        let total_to_be_reviewed_count = flashcards.iter().filter(|f| f.next_review <= now).count();
+        let mut queue = Vec::new();
+        let mut seen_notes = HashSet::new();
+        for flashcard in due {
+            let note = match &flashcard.note {
+                Some(note) => note,
+                None => {
+                    if take_new(&[flashcard]) {
+                        queue.push(flashcard.question.clone());
+                    }
+                    continue;
+                }
+            };
+            if seen_notes.contains(note) {
+                continue;
+            }
+            if self.settings.note_review_decks.contains(&flashcard.deck) {
+                // Whether the note can be reviewed doesn't depend on which
+                // of its cards is due, so it is only considered once.
+                seen_notes.insert(note);
+                let siblings = self.note_siblings(note);
+                if siblings.iter().all(|f| window_open(f)) && take_new(&siblings) {
+                    queue.extend(siblings.iter().map(|f| f.question.clone()));
+                }
+            } else if take_new(&[flashcard]) {
+                seen_notes.insert(note);
+                queue.push(flashcard.question.clone());
+            }
+        }
+        Ok(queue)
+    }
+
+    /// Schedules every card of a note by its weakest cloze, so that a note
+    /// reviewed as a whole also comes due as a whole.
+    fn align_note(&mut self, note: &str) {
+        let next_review = self
+            .note_siblings(note)
+            .iter()
+            .map(|f| f.next_review)
+            .min();
+        if let Some(next_review) = next_review {
+            for flashcard in self.flashcards.values_mut() {
+                if !flashcard.draft && flashcard.note.as_deref() == Some(note) {
+                    flashcard.next_review = next_review;
+                }
+            }
+        }
+    }
+
+    fn review_flashcards(&mut self) -> io::Result<()> {
+        let now = now_secs()?;
+        let queue = self.review_queue(now)?;
+
+        let total_to_be_reviewed_count = queue.len();
         let mut review_count = 0;
+        let mut follow_ups = Vec::new();
+        let mut graded = HashSet::new();
+        let reviewed_before = self.review_log.len();
 
-        for flashcard in flashcards {
-            if flashcard.next_review <= now {
-                review_count += 1;
-                println!("Review {}/{}:", review_count, total_to_be_reviewed_count);
-                println!("Question: {}", flashcard.question);
-                println!("Hint: {}", flashcard.guidance);
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-                println!("Answer: {}", flashcard.answer);
-                println!("How well did you remember? (0-5):");
-                let mut performance = String::new();
+        for question in &queue {
+            let flashcard = match self.flashcards.get_mut(question) {
+                Some(flashcard) => flashcard,
+                None => continue,
+            };
+            let new_card = flashcard.is_new();
+            review_count += 1;
+            println!("Review {}/{}:", review_count, total_to_be_reviewed_count);
+            emit_event(
+                &mut self.events,
+                ReviewEvent::CardShown {
+                    question: &flashcard.question,
+                    deck: &flashcard.deck,
+                    position: review_count,
+                    total: total_to_be_reviewed_count,
+                    at: now_secs()?,
+                },
+            );
+            let shown_at = Instant::now();
+            println!("Question: {}", flashcard.question);
+            println!("Hint: {}", flashcard.guidance);
+            let typed_answer = prompt("Type your answer (or press Enter to reveal):")?;
+            let confidence = if self.settings.ask_confidence {
+                Confidence::parse(&prompt("How sure are you? (s = sure, u = unsure):")?)
+            } else {
+                None
+            };
+            println!("Answer: {}", flashcard.answer);
+            let mut performance = String::new();
+            loop {
+                println!("How well did you remember? (0-5, c to add a follow-up card):");
+                performance.clear();
                 io::stdin().read_line(&mut performance)?;
-                let performance: u32 = match performance.trim().parse() {
-                    Ok(n) => n,
-                    Err(_) => {
-                        eprintln!("Invalid performance input");
-                        continue;
-                    },
-                };
-                flashcard.update(performance);
-                println!();
-
-                if review_count % self.batch_size == 0 {
-                    println!("You have reviewed {} flashcards. Do you want to continue? (y/n):", self.batch_size);
-                    let mut choice = String::new();
-                    io::stdin().read_line(&mut choice)?;
-                    if choice.trim().to_lowercase() != "y" {
-                        break;
-                    }
+                if performance.trim() != "c" {
+                    break;
+                }
+                follow_ups.push(prompt_flashcard(&flashcard.deck)?);
+            }
+            let performance: u32 = match performance.trim().parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    eprintln!("Invalid performance input");
+                    continue;
+                },
+            };
+            flashcard.update(performance);
+            graded.insert(question.clone());
+            self.review_log.push(ReviewRecord {
+                question: flashcard.question.clone(),
+                reviewed_at: now_secs()?,
+                performance,
+                interval: flashcard.interval,
+                confidence,
+                new_card,
+                typed_answer: Some(typed_answer).filter(|a| !a.is_empty()),
+                duration_secs: shown_at.elapsed().as_secs(),
+            });
+            emit_event(
+                &mut self.events,
+                ReviewEvent::Graded {
+                    question: &flashcard.question,
+                    deck: &flashcard.deck,
+                    performance,
+                    interval: flashcard.interval,
+                    next_review: flashcard.next_review,
+                    at: now_secs()?,
+                },
+            );
+            println!();
+
+            if review_count % self.batch_size == 0 {
+                println!("You have reviewed {} flashcards. Do you want to continue? (y/n):", self.batch_size);
+                let mut choice = String::new();
+                io::stdin().read_line(&mut choice)?;
+                if choice.trim().to_lowercase() != "y" {
+                    break;
                 }
             }
         }
 
+        let reviewed_notes: HashSet<String> = graded
+            .iter()
+            .filter_map(|q| self.flashcards[q].note.clone())
+            .collect();
+        for note in reviewed_notes {
+            let siblings = self.note_siblings(&note);
+            let reviewed_whole = self.settings.note_review_decks.contains(&siblings[0].deck)
+                && siblings.iter().all(|f| graded.contains(&f.question));
+            if reviewed_whole {
+                self.align_note(&note);
+            }
+        }
+
+        emit_event(
+            &mut self.events,
+            ReviewEvent::SessionEnded {
+                reviewed: self.review_log.len() - reviewed_before,
+                at: now_secs()?,
+            },
+        );
+
+        for (question, answer, guidance, deck) in follow_ups {
+            self.add_flashcard(question, answer, guidance, deck, self.settings.capture_as_draft);
+        }
+        if self.settings.ask_confidence {
+            for suggestion in Calibration::from_log(&self.review_log).suggestions() {
+                println!("{}", suggestion);
+            }
+        }
         self.save()?;
         Ok(())
     }
 
+    fn browse_flashcards(&self) -> io::Result<()> {
+        let now = now_secs()?;
+        let root = collator_for(None)?;
+        // Deduplicate by exact name before sorting: names the collator treats
+        // as equal (e.g. NFC and NFD spellings) can interleave after the sort.
+        let decks: BTreeSet<&str> = self.flashcards.values().map(|f| f.deck.as_str()).collect();
+        let mut decks: Vec<&str> = decks.into_iter().collect();
+        decks.sort_by(|a, b| root.compare(a, b));
+
+        for deck in decks {
+            let collator = collator_for(self.settings.deck_languages.get(deck).map(|l| l.as_str()))?;
+            let mut flashcards: Vec<&Flashcard> =
+                self.flashcards.values().filter(|f| f.deck == deck).collect();
+            flashcards.sort_by(|a, b| collator.compare(&a.question, &b.question));
+
+            println!("Deck: {}", deck);
+            for flashcard in flashcards {
+                if flashcard.draft {
+                    println!("  {} (draft)", flashcard.question);
+                } else if flashcard.next_review <= now {
+                    println!("  {} (due)", flashcard.question);
+                } else {
+                    let days = (flashcard.next_review - now).div_ceil(86400);
+                    println!("  {} (due in {} days)", flashcard.question, days);
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn save(&self) -> io::Result<()> {
         let flashcards: Vec<Flashcard> = self.flashcards.values().cloned().collect();
         let data = serde_json::to_string(&flashcards)?;
         fs::write(&self.flashcards_file, data)?;
+        let data = serde_json::to_string(&self.review_log)?;
+        fs::write(&self.review_log_file, data)?;
         Ok(())
     }
 
+    fn save_settings(&self) -> io::Result<()> {
+        let data = serde_json::to_string(&self.settings)?;
+        fs::write(&self.settings_file, data)?;
+        Ok(())
+    }
+
+    /// Loads the cards first, then the review log and settings. A missing
+    /// file is treated as empty, but a corrupt one is an error: carrying on
+    /// would overwrite it on the next save.
     fn load(&mut self) -> io::Result<()> {
-        let data = fs::read_to_string(&self.flashcards_file)?;
-        let flashcards: Vec<Flashcard> = serde_json::from_str(&data)?;
+        let flashcards: Vec<Flashcard> = read_json_or_default(&self.flashcards_file)?;
         for flashcard in flashcards {
             self.flashcards
                 .insert(flashcard.question.clone(), flashcard);
         }
+        self.review_log = read_json_or_default(&self.review_log_file)?;
+        self.settings = read_json_or_default(&self.settings_file)?;
         Ok(())
     }
 }
 
-fn main() -> io::Result<()> {
+const USAGE: &str = "Usage:
+  words
+  words review [--events-fd <fd> | --events-socket <path>]
+  words snapshot create <name>
+  words snapshot list
+  words snapshot rollback <id>
+  words mistakes [<question> | --deck <deck>]
+  words stats compare <from-date> <to-date>
+  words add <question> <answer> [<hint>] [--deck <deck>] [--draft]
+  words drafts [edit <question> | promote <question> | promote --all | promote --deck <deck> | delete <question>]
+  words plan --exam-date <date> [--deck <deck>] [--format markdown|ics] [--output <file>] [--accept | --clear]
+  words import-highlights <kindle|readwise|hypothesis> <file> [--deck <deck>] [--ask]
+  words serve [<addr>]
+  words token create [--read-only] [--rate <requests-per-minute>]
+  words token list
+  words token revoke <id>";
+
+/// An `InvalidInput` error describing the problem, followed by the forms of
+/// one subcommand from `USAGE`.
+fn usage_error(command: &str, problem: &str) -> io::Error {
+    let prefix = format!("words {}", command);
+    let forms: Vec<&str> = USAGE
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.strip_prefix(&prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(' ')))
+        .collect();
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}\n\nUsage:\n  {}", problem, forms.join("\n  ")),
+    )
+}
+
+fn now_secs() -> io::Result<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|n| n.as_secs())
+        .map_err(|_| io::Error::other("SystemTime error"))
+}
+
+/// Builds a collator for a BCP-47 language tag, falling back to the root
+/// collation order when no language is configured.
+fn collator_for(language: Option<&str>) -> io::Result<CollatorBorrowed<'static>> {
+    let prefs = match language.map(Locale::try_from_str) {
+        Some(Ok(locale)) => CollatorPreferences::from(&locale),
+        _ => CollatorPreferences::default(),
+    };
+    Collator::try_new(prefs, CollatorOptions::default()).map_err(io::Error::other)
+}
+
+fn local_midnight(date: NaiveDate) -> io::Result<u64> {
+    let midnight = date
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .ok_or_else(|| io::Error::other(format!("cannot determine the start of {}", date)))?;
+    Ok(midnight.timestamp().max(0) as u64)
+}
+
+fn start_of_today() -> io::Result<u64> {
+    local_midnight(Local::now().date_naive())
+}
+
+fn read_json_or_default<T: DeserializeOwned + Default>(path: impl AsRef<Path>) -> io::Result<T> {
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> io::Result<()> {
     let batch_size = 5;
     let flashcards_file = "flashcards.json".to_string();
-    let mut manager = SpacedRepetitionManager::new(batch_size, flashcards_file);
+    let review_log_file = "review_log.json".to_string();
+    let settings_file = "settings.json".to_string();
+    let snapshot_dir = "snapshots";
+    let mut manager = SpacedRepetitionManager::new(
+        batch_size,
+        flashcards_file,
+        review_log_file,
+        settings_file,
+    );
 
     // Load progress if file exists.
-    let _ = manager.load();
+    manager
+        .load()
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to load progress: {}", e)))?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("review") => {
+            manager.events = open_event_sink(&args[1..])?;
+            return manager.review_flashcards();
+        }
+        Some("snapshot") => {
+            return run_snapshot_command(&mut manager, &SnapshotStore::new(snapshot_dir), &args[1..]);
+        }
+        Some("serve") => {
+            let addr = args.get(1).map(String::as_str).unwrap_or(server::DEFAULT_ADDR);
+            return server::serve(&mut manager, addr);
+        }
+        Some("token") => {
+            return server::run_token_command(&mut manager, &args[1..]);
+        }
+        Some("plan") => return plan::run_plan_command(&mut manager, &args[1..]),
+        Some("add") => return quick_add(&mut manager, &args[1..]),
+        Some("drafts") => return drafts::run_drafts_command(&mut manager, &args[1..]),
+        Some("import-highlights") => {
+            if args.len() < 3 {
+                return Err(usage_error(
+                    "import-highlights",
+                    "expected a format and a file",
+                ));
+            }
+            let mut deck = default_deck();
+            let mut ask = false;
+            let mut options = args[3..].iter();
+            while let Some(option) = options.next() {
+                match option.as_str() {
+                    "--deck" => match options.next() {
+                        Some(name) => deck = name.clone(),
+                        None => return Err(usage_error("import-highlights", "--deck expects a deck name")),
+                    },
+                    "--ask" => ask = true,
+                    _ => {
+                        return Err(usage_error(
+                            "import-highlights",
+                            &format!("unknown option: {}", option),
+                        ));
+                    }
+                }
+            }
+            return highlights::import(&mut manager, &args[1], &args[2], &deck, ask);
+        }
+        Some("stats") => {
+            return match &args[1..] {
+                [command, from, to] if command == "compare" => {
+                    stats::print_comparison(&manager.flashcards, &manager.review_log, from, to)
+                }
+                _ => Err(usage_error("stats", "invalid stats command")),
+            };
+        }
+        Some("mistakes") => {
+            match &args[1..] {
+                [] => mistakes::print_report(&manager.flashcards, &manager.review_log, None),
+                [flag, deck] if flag == "--deck" => {
+                    mistakes::print_report(&manager.flashcards, &manager.review_log, Some(deck))
+                }
+                [flag] if flag == "--deck" => {
+                    return Err(usage_error("mistakes", "--deck expects a deck name"))
+                }
+                [question] if !question.starts_with("--") => {
+                    mistakes::print_card_mistakes(&manager.flashcards, &manager.review_log, question)
+                }
+                _ => return Err(usage_error("mistakes", "invalid mistakes command")),
+            }
+            return Ok(());
+        }
+        Some(command) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown command: {}\n\n{}", command, USAGE),
+            ));
+        }
+        None => {}
+    }
 
     loop {
         println!("Choose an option:");
         println!("1. Review Flashcards");
         println!("2. Add Flashcard");
         println!("3. Import Flashcards from CSV");
+        println!("4. Show Confidence Calibration");
+        println!("5. Settings");
+        println!("6. Browse Flashcards");
+        println!("7. Show Common Mistakes");
         println!("x. Exit");
         let mut choice = String::new();
         io::stdin().read_line(&mut choice)?;
@@ -195,6 +965,10 @@ fn main() -> io::Result<()> {
             "1" => manager.review_flashcards()?,
             "2" => add_flashcard(&mut manager)?,
             "3" => import_flashcards(&mut manager)?,
+            "4" => Calibration::from_log(&manager.review_log).print(),
+            "5" => edit_settings(&mut manager)?,
+            "6" => manager.browse_flashcards()?,
+            "7" => mistakes::print_report(&manager.flashcards, &manager.review_log, None),
             "x" => break,
             _ => println!("Invalid option. Please try again."),
         }
@@ -203,25 +977,165 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
+fn run_snapshot_command(
+    manager: &mut SpacedRepetitionManager,
+    store: &SnapshotStore,
+    args: &[String],
+) -> io::Result<()> {
+    match args {
+        [command, name] if command == "create" => {
+            let id = store.create(name, &manager.flashcards)?;
+            println!("Created snapshot {} ({} cards)", id, manager.flashcards.len());
+        }
+        [command] if command == "list" => {
+            for snapshot in store.list()? {
+                println!("{}  {}  {}", snapshot.id, snapshot.created_at, snapshot.name);
+            }
+        }
+        [command, id] if command == "rollback" => {
+            let id = id
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "snapshot id must be a number"))?;
+            let flashcards = store.restore(id)?;
+            let backup_id = store.create(&format!("before rollback to {}", id), &manager.flashcards)?;
+            manager.flashcards = flashcards
+                .into_iter()
+                .map(|f| (f.question.clone(), f))
+                .collect();
+            manager.save()?;
+            println!(
+                "Rolled back to snapshot {} ({} cards). Previous state saved as snapshot {}.",
+                id,
+                manager.flashcards.len(),
+                backup_id
+            );
+        }
+        _ => return Err(usage_error("snapshot", "invalid snapshot command")),
+    }
+    Ok(())
+}
+
+/// Opens the destination for `words review` JSON events: an inherited file
+/// descriptor (`--events-fd 3`) or a listening Unix socket (`--events-socket`).
+fn open_event_sink(args: &[String]) -> io::Result<Option<Box<dyn Write>>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    match args {
+        [] => Ok(None),
+        [flag, fd] if flag == "--events-fd" => {
+            let fd = fd
+                .parse()
+                .map_err(|_| invalid("--events-fd expects a file descriptor number"))?;
+            open_event_fd(fd).map(Some)
+        }
+        [flag, path] if flag == "--events-socket" => open_event_socket(path).map(Some),
+        _ => Err(usage_error("review", "invalid review options")),
+    }
+}
+
+#[cfg(unix)]
+fn open_event_fd(fd: i32) -> io::Result<Box<dyn Write>> {
+    use std::os::fd::FromRawFd;
+    if fd <= 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--events-fd cannot be stdin, stdout or stderr",
+        ));
+    }
+    // SAFETY: F_GETFD only reads the descriptor flags and is harmless on a
+    // descriptor that is not open.
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(
+            e.kind(),
+            format!("--events-fd {}: {}", fd, e),
+        ));
+    }
+    // SAFETY: the descriptor is open and above stdio, and nothing else in
+    // this process opens or uses descriptors by number, so the event stream
+    // is its only owner.
+    Ok(Box::new(unsafe { File::from_raw_fd(fd) }))
+}
+
+#[cfg(unix)]
+fn open_event_socket(path: &str) -> io::Result<Box<dyn Write>> {
+    Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn open_event_fd(_fd: i32) -> io::Result<Box<dyn Write>> {
+    Err(io::Error::other("--events-fd is only supported on Unix"))
+}
+
+#[cfg(not(unix))]
+fn open_event_socket(_path: &str) -> io::Result<Box<dyn Write>> {
+    Err(io::Error::other("--events-socket is only supported on Unix"))
+}
+
+fn prompt(message: &str) -> io::Result<String> {
+    println!("{}", message);
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+fn prompt_flashcard(default_deck: &str) -> io::Result<(String, String, String, String)> {
+    let question = prompt("Enter the question:")?;
+    let answer = prompt("Enter the answer:")?;
+    let guidance = prompt("Enter a hint or guidance:")?;
+    let deck = prompt(&format!("Enter the deck:(default: {})", default_deck))?;
+    let deck = if deck.is_empty() {
+        default_deck.to_string()
+    } else {
+        deck
+    };
+    Ok((question, answer, guidance, deck))
+}
+
 fn add_flashcard(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
-    println!("Enter the question:");
-    let mut question = String::new();
-    io::stdin().read_line(&mut question)?;
-    println!("Enter the answer:");
-    let mut answer = String::new();
-    io::stdin().read_line(&mut answer)?;
-    println!("Enter a hint or guidance:");
-    let mut guidance = String::new();
-    io::stdin().read_line(&mut guidance)?;
-    manager.add_flashcard(
-        question.trim().to_string(),
-        answer.trim().to_string(),
-        guidance.trim().to_string(),
-    );
+    let (question, answer, guidance, deck) = prompt_flashcard(DEFAULT_DECK)?;
+    let draft = manager.settings.capture_as_draft;
+    manager.add_flashcard(question, answer, guidance, deck, draft);
     manager.save()?;
     Ok(())
 }
 
+/// Adds a card straight from the command line, e.g. to capture a question
+/// as a draft without opening the menu.
+fn quick_add(manager: &mut SpacedRepetitionManager, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut fields = Vec::new();
+    let mut deck = default_deck();
+    let mut draft = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--deck" => {
+                deck = args
+                    .next()
+                    .ok_or_else(|| invalid("--deck expects a deck name".to_string()))?
+                    .clone()
+            }
+            "--draft" => draft = true,
+            option if option.starts_with("--") => {
+                return Err(invalid(format!("unknown option: {}", option)))
+            }
+            field => fields.push(field.to_string()),
+        }
+    }
+    let (question, answer, guidance) = match fields.as_slice() {
+        [question, answer] => (question.clone(), answer.clone(), String::new()),
+        [question, answer, guidance] => (question.clone(), answer.clone(), guidance.clone()),
+        _ => {
+            return Err(usage_error(
+                "add",
+                "expected a question, an answer and an optional hint",
+            ))
+        }
+    };
+    manager.add_flashcard(question, answer, guidance, deck, draft);
+    manager.save()
+}
+
 fn import_flashcards(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
     println!("Enter the path to the CSV file:(default: flashcards.csv)");
     let mut file_path = String::new();
@@ -231,6 +1145,292 @@ fn import_flashcards(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
     } else {
         file_path
     };
-    manager.batch_add_flashcards(file_path.trim())?;
+    let draft = prompt("Import as drafts? (y/n):")?.to_lowercase() == "y";
+    manager.batch_add_flashcards(file_path.trim(), draft)?;
     Ok(())
 }
+
+fn edit_settings(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
+    loop {
+        println!("Settings:");
+        println!(
+            "1. Ask for confidence before revealing answers (currently {})",
+            if manager.settings.ask_confidence { "on" } else { "off" }
+        );
+        println!("2. Set a deck's language for sorting");
+        match manager.settings.new_cards_per_day {
+            Some(limit) => println!("3. Set new cards per day (currently {})", limit),
+            None => println!("3. Set new cards per day (currently unlimited)"),
+        }
+        println!(
+            "4. Reduce new cards when the review backlog exceeds {} cards (currently {})",
+            manager.settings.backlog_threshold,
+            match (manager.settings.adaptive_new_cards, manager.settings.new_cards_per_day) {
+                (false, _) => "off",
+                (true, Some(_)) => "on",
+                (true, None) => "on, but inactive without a daily limit",
+            }
+        );
+        println!("5. Set review backlog threshold");
+        println!("6. Set study windows for a deck");
+        println!("7. Set study windows for a card");
+        println!(
+            "8. Save cards added from the menu or during review as drafts (currently {})",
+            if manager.settings.capture_as_draft { "on" } else { "off" }
+        );
+        println!("9. Review all clozes of a note together in a deck");
+        println!("x. Back");
+        let mut choice = String::new();
+        io::stdin().read_line(&mut choice)?;
+
+        match choice.trim() {
+            "1" => manager.settings.ask_confidence = !manager.settings.ask_confidence,
+            "2" => set_deck_language(manager)?,
+            "3" => {
+                let limit = prompt("Enter the number of new cards per day, or leave empty for unlimited:")?;
+                if limit.is_empty() {
+                    manager.settings.new_cards_per_day = None;
+                    if manager.settings.adaptive_new_cards {
+                        manager.settings.adaptive_new_cards = false;
+                        println!("Turned off reducing new cards for the backlog, which needs a daily limit.");
+                    }
+                } else {
+                    match limit.parse() {
+                        Ok(limit) => manager.settings.new_cards_per_day = Some(limit),
+                        Err(_) => println!("Invalid number: {}", limit),
+                    }
+                }
+            }
+            "4" => {
+                if !manager.settings.adaptive_new_cards && manager.settings.new_cards_per_day.is_none() {
+                    println!("Set the number of new cards per day first (option 3).");
+                } else {
+                    manager.settings.adaptive_new_cards = !manager.settings.adaptive_new_cards;
+                }
+            }
+            "5" => {
+                let threshold = prompt("Enter the review backlog threshold:")?;
+                match threshold.parse() {
+                    Ok(threshold) => manager.settings.backlog_threshold = threshold,
+                    Err(_) => println!("Invalid number: {}", threshold),
+                }
+            }
+            "6" => set_deck_windows(manager)?,
+            "7" => set_card_windows(manager)?,
+            "8" => manager.settings.capture_as_draft = !manager.settings.capture_as_draft,
+            "9" => set_note_review(manager)?,
+            "x" => break,
+            _ => println!("Invalid option. Please try again."),
+        }
+    }
+    manager.save_settings()
+}
+
+fn set_deck_language(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
+    let deck = prompt(&format!("Enter the deck:(default: {})", DEFAULT_DECK))?;
+    let deck = if deck.is_empty() {
+        default_deck()
+    } else {
+        deck
+    };
+    let language = prompt("Enter the language tag (e.g. de, ja), or leave empty to reset:")?;
+    if language.is_empty() {
+        manager.settings.deck_languages.remove(&deck);
+    } else if Locale::try_from_str(&language).is_ok() {
+        manager.settings.deck_languages.insert(deck, language);
+    } else {
+        println!("Invalid language tag: {}", language);
+    }
+    Ok(())
+}
+
+fn prompt_windows(current: &[StudyWindow]) -> io::Result<Option<Vec<StudyWindow>>> {
+    println!("Current study windows: {}", window::format_windows(current));
+    let spec = prompt("Enter study windows (e.g. mon-fri 9-18; sat,sun 10-12), or leave empty for any time:")?;
+    match window::parse_windows(&spec) {
+        Ok(windows) => Ok(Some(windows)),
+        Err(e) => {
+            println!("Invalid study windows: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+fn set_deck_windows(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
+    let deck = prompt(&format!("Enter the deck:(default: {})", DEFAULT_DECK))?;
+    let deck = if deck.is_empty() {
+        default_deck()
+    } else {
+        deck
+    };
+    let current = manager.settings.deck_windows.get(&deck).cloned().unwrap_or_default();
+    match prompt_windows(&current)? {
+        Some(windows) if windows.is_empty() => {
+            manager.settings.deck_windows.remove(&deck);
+        }
+        Some(windows) => {
+            manager.settings.deck_windows.insert(deck, windows);
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+fn set_card_windows(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
+    let question = prompt("Enter the question of the card:")?;
+    let flashcard = match manager.flashcards.get_mut(&question) {
+        Some(flashcard) => flashcard,
+        None => {
+            println!("No card with question: {}", question);
+            return Ok(());
+        }
+    };
+    if let Some(windows) = prompt_windows(&flashcard.windows)? {
+        flashcard.windows = windows;
+        manager.save()?;
+    }
+    Ok(())
+}
+
+fn set_note_review(manager: &mut SpacedRepetitionManager) -> io::Result<()> {
+    let deck = prompt(&format!("Enter the deck:(default: {})", DEFAULT_DECK))?;
+    let deck = if deck.is_empty() {
+        default_deck()
+    } else {
+        deck
+    };
+    let enabled = manager.settings.note_review_decks.contains(&deck);
+    println!(
+        "Cloze notes in {} are currently reviewed {}.",
+        deck,
+        if enabled { "as a whole" } else { "as independent cards" }
+    );
+    let choice = prompt("Review them as a whole? (y/n):")?.to_lowercase();
+    if choice == "y" {
+        manager.settings.note_review_decks.insert(deck);
+    } else if choice == "n" {
+        manager.settings.note_review_decks.remove(&deck);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager holding one two-cloze note in a note-level deck.
+    fn cloze_manager(settings: Settings) -> SpacedRepetitionManager {
+        let mut manager =
+            SpacedRepetitionManager::new(5, String::new(), String::new(), String::new());
+        manager.settings = Settings {
+            note_review_decks: HashSet::from([default_deck()]),
+            ..settings
+        };
+        manager.add_flashcard(
+            "{{c1::A}} and {{c2::B}}".to_string(),
+            String::new(),
+            String::new(),
+            default_deck(),
+            false,
+        );
+        manager
+    }
+
+    fn introduce(manager: &mut SpacedRepetitionManager, question: &str) {
+        let mut flashcard = Flashcard::new(
+            question.to_string(),
+            String::new(),
+            String::new(),
+            default_deck(),
+        );
+        flashcard.update(4);
+        manager.insert_flashcard(flashcard);
+        manager.review_log.push(ReviewRecord {
+            question: question.to_string(),
+            reviewed_at: now_secs().unwrap(),
+            performance: 4,
+            interval: 1,
+            confidence: None,
+            new_card: true,
+            typed_answer: None,
+            duration_secs: 0,
+        });
+    }
+
+    #[test]
+    fn note_may_overdraw_an_untouched_daily_limit() {
+        let manager = cloze_manager(Settings {
+            new_cards_per_day: Some(1),
+            ..Settings::default()
+        });
+        let queue = manager.review_queue(now_secs().unwrap()).unwrap();
+        assert_eq!(queue, ["[...] and B", "A and [...]"]);
+    }
+
+    #[test]
+    fn note_waits_once_the_daily_limit_is_touched() {
+        let mut manager = cloze_manager(Settings {
+            new_cards_per_day: Some(2),
+            ..Settings::default()
+        });
+        introduce(&mut manager, "earlier");
+        let queue = manager.review_queue(now_secs().unwrap()).unwrap();
+        assert!(queue.is_empty(), "{:?}", queue);
+    }
+
+    #[test]
+    fn note_may_overdraw_an_untouched_plan() {
+        let exam_date = Local::now().date_naive() + chrono::Duration::days(60);
+        let manager = cloze_manager(Settings {
+            deck_exams: HashMap::from([(default_deck(), exam_date.to_string())]),
+            ..Settings::default()
+        });
+        assert_eq!(plan::new_cards_remaining(&manager).unwrap()[DEFAULT_DECK], 1);
+        let queue = manager.review_queue(now_secs().unwrap()).unwrap();
+        assert_eq!(queue.len(), 2);
+    }
+
+    fn adaptive(limit: usize, threshold: usize) -> Settings {
+        Settings {
+            new_cards_per_day: Some(limit),
+            adaptive_new_cards: true,
+            backlog_threshold: threshold,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn new_card_limit_without_daily_limit_is_unlimited() {
+        let settings = Settings {
+            adaptive_new_cards: true,
+            ..Settings::default()
+        };
+        assert_eq!(settings.new_card_limit(1000), None);
+    }
+
+    #[test]
+    fn new_card_limit_ignores_backlog_unless_adaptive() {
+        let settings = Settings {
+            new_cards_per_day: Some(20),
+            ..Settings::default()
+        };
+        assert_eq!(settings.new_card_limit(1000), Some(20));
+    }
+
+    #[test]
+    fn new_card_limit_shrinks_between_threshold_and_twice_threshold() {
+        let settings = adaptive(20, 50);
+        assert_eq!(settings.new_card_limit(0), Some(20));
+        assert_eq!(settings.new_card_limit(50), Some(20));
+        assert_eq!(settings.new_card_limit(75), Some(10));
+        assert_eq!(settings.new_card_limit(100), Some(0));
+        assert_eq!(settings.new_card_limit(500), Some(0));
+    }
+
+    #[test]
+    fn new_card_limit_with_zero_threshold_stops_at_any_backlog() {
+        let settings = adaptive(20, 0);
+        assert_eq!(settings.new_card_limit(0), Some(20));
+        assert_eq!(settings.new_card_limit(1), Some(0));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{Flashcard, ReviewRecord, PASSING_PERFORMANCE};
+use std::collections::HashMap;
+
+const REPORT_SIZE: usize = 10;
+
+fn normalize(answer: &str) -> String {
+    answer.trim().to_lowercase()
+}
+
+/// Typed answers from failed reviews, counted per card and normalized so
+/// that differences in case and surrounding whitespace don't split counts.
+fn count_mistakes(review_log: &[ReviewRecord]) -> HashMap<(&str, String), usize> {
+    let mut counts = HashMap::new();
+    for record in review_log {
+        if record.performance >= PASSING_PERFORMANCE {
+            continue;
+        }
+        if let Some(typed) = record.typed_answer.as_deref() {
+            *counts
+                .entry((record.question.as_str(), normalize(typed)))
+                .or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Names the card a mistaken answer belongs to, when it matches another
+/// card's answer or question — the pairs worth a contrast card.
+fn confused_with<'a>(
+    flashcards: &'a HashMap<String, Flashcard>,
+    question: &str,
+    typed: &str,
+) -> Option<&'a str> {
+    flashcards
+        .values()
+        .find(|f| {
+            f.question != question
+                && (normalize(&f.answer) == typed || normalize(&f.question) == typed)
+        })
+        .map(|f| f.question.as_str())
+}
+
+fn print_mistake(flashcards: &HashMap<String, Flashcard>, question: &str, typed: &str, count: usize) {
+    match confused_with(flashcards, question, typed) {
+        Some(other) => println!("  {}x \"{}\" (matches card \"{}\")", count, typed, other),
+        None => println!("  {}x \"{}\"", count, typed),
+    }
+}
+
+pub fn print_card_mistakes(
+    flashcards: &HashMap<String, Flashcard>,
+    review_log: &[ReviewRecord],
+    question: &str,
+) {
+    let flashcard = match flashcards.get(question) {
+        Some(flashcard) => flashcard,
+        None => {
+            println!("No card with question: {}", question);
+            return;
+        }
+    };
+    let mut mistakes: Vec<(String, usize)> = count_mistakes(review_log)
+        .into_iter()
+        .filter(|((q, _), _)| *q == question)
+        .map(|((_, typed), count)| (typed, count))
+        .collect();
+    if mistakes.is_empty() {
+        println!("No mistakes recorded for: {}", question);
+        return;
+    }
+    mistakes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    println!("Question: {}", flashcard.question);
+    println!("Answer: {}", flashcard.answer);
+    for (typed, count) in mistakes {
+        print_mistake(flashcards, question, &typed, count);
+    }
+}
+
+/// Prints the most frequent mistakes per deck, optionally for one deck only.
+pub fn print_report(
+    flashcards: &HashMap<String, Flashcard>,
+    review_log: &[ReviewRecord],
+    deck: Option<&str>,
+) {
+    let mut by_deck: HashMap<&str, Vec<(&str, String, usize)>> = HashMap::new();
+    for ((question, typed), count) in count_mistakes(review_log) {
+        let card_deck = match flashcards.get(question) {
+            Some(flashcard) => flashcard.deck.as_str(),
+            None => continue,
+        };
+        if deck.is_some_and(|d| d != card_deck) {
+            continue;
+        }
+        by_deck
+            .entry(card_deck)
+            .or_default()
+            .push((question, typed, count));
+    }
+    if by_deck.is_empty() {
+        println!("No mistakes recorded yet.");
+        return;
+    }
+
+    let mut decks: Vec<_> = by_deck.into_iter().collect();
+    decks.sort_by(|a, b| a.0.cmp(b.0));
+    for (deck, mut mistakes) in decks {
+        mistakes.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        println!("Deck: {}", deck);
+        for (question, typed, count) in mistakes.into_iter().take(REPORT_SIZE) {
+            println!("{}", question);
+            print_mistake(flashcards, question, &typed, count);
+        }
+    }
+}
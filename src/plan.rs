@@ -0,0 +1,486 @@
+use crate::{local_midnight, usage_error, Flashcard, SpacedRepetitionManager};
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+/// Days before the exam in which no new cards are introduced, so the last
+/// ones get a few reviews. Shortened for plans of under five weeks.
+const CONSOLIDATION_DAYS: i64 = 7;
+const THROUGHPUT_WINDOW_DAYS: i64 = 30;
+/// Early on, spare review capacity may bring new cards forward, but only
+/// this share of it (each new card brings several reviews of its own)...
+const SPARE_CAPACITY_DIVISOR: usize = 4;
+/// ...and never more than this many percent above the even pace.
+const MAX_AHEAD_PERCENT: usize = 50;
+/// The grade assumed when projecting future reviews.
+const ASSUMED_PERFORMANCE: u32 = 4;
+
+struct PlanDay {
+    date: NaiveDate,
+    new_cards: usize,
+    reviews: usize,
+}
+
+struct StudyPlan {
+    deck: String,
+    exam_date: NaiveDate,
+    throughput: Option<usize>,
+    days: Vec<PlanDay>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_date(date: &str) -> io::Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| invalid(format!("expected a date like 2025-06-10, got '{}'", date)))
+}
+
+fn local_date(timestamp: u64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(timestamp as i64, 0).map(|t| t.with_timezone(&Local).date_naive())
+}
+
+/// Average reviews of a deck per study day over the last 30 days, or `None`
+/// without any recent history.
+fn throughput(manager: &SpacedRepetitionManager, deck: &str, today: NaiveDate) -> Option<usize> {
+    let since = today - Duration::days(THROUGHPUT_WINDOW_DAYS);
+    let dates: Vec<NaiveDate> = manager
+        .review_log
+        .iter()
+        .filter(|r| {
+            manager
+                .flashcards
+                .get(&r.question)
+                .is_some_and(|f| f.deck == deck)
+        })
+        .filter_map(|r| local_date(r.reviewed_at))
+        .filter(|date| *date >= since && *date < today)
+        .collect();
+    let active_days = dates.iter().collect::<HashSet<_>>().len();
+    (active_days > 0).then(|| dates.len().div_ceil(active_days))
+}
+
+/// Projects the review dates of a card up to and including `last`, assuming
+/// every review is graded as remembered.
+fn project_reviews(
+    mut flashcard: Flashcard,
+    mut date: NaiveDate,
+    last: NaiveDate,
+    reviews: &mut HashMap<NaiveDate, usize>,
+) {
+    loop {
+        flashcard.update(ASSUMED_PERFORMANCE);
+        date += Duration::days(flashcard.interval.max(1) as i64);
+        if date > last {
+            break;
+        }
+        *reviews.entry(date).or_default() += 1;
+    }
+}
+
+fn is_planned(flashcard: &Flashcard, deck: &str) -> bool {
+    !flashcard.draft && flashcard.deck == deck
+}
+
+/// Plans the new cards of a deck from `today` up to the day before the
+/// exam. The cards of the deck already introduced today count towards
+/// today's new cards and today's pace, so re-planning during the day gives
+/// the same numbers.
+fn generate(
+    manager: &SpacedRepetitionManager,
+    deck: &str,
+    exam_date: NaiveDate,
+    today: NaiveDate,
+    introduced_today: usize,
+) -> io::Result<StudyPlan> {
+    let total_days = (exam_date - today).num_days();
+    if total_days <= 0 {
+        return Err(invalid(format!(
+            "the exam date {} is not in the future",
+            exam_date
+        )));
+    }
+    let last_day = exam_date - Duration::days(1);
+    let consolidation = CONSOLIDATION_DAYS.min(total_days / 5);
+    let introduction_days = (total_days - consolidation) as usize;
+
+    let mut reviews: HashMap<NaiveDate, usize> = HashMap::new();
+    for flashcard in manager.flashcards.values() {
+        if !is_planned(flashcard, deck) || flashcard.is_new() {
+            continue;
+        }
+        let due = local_date(flashcard.next_review)
+            .unwrap_or(today)
+            .max(today);
+        if due > last_day {
+            continue;
+        }
+        *reviews.entry(due).or_default() += 1;
+        project_reviews(flashcard.clone(), due, last_day, &mut reviews);
+    }
+
+    let throughput = throughput(manager, deck, today);
+    let mut remaining = manager
+        .flashcards
+        .values()
+        .filter(|f| is_planned(f, deck) && f.is_new())
+        .count();
+    let template = Flashcard::new(
+        String::new(),
+        String::new(),
+        String::new(),
+        deck.to_string(),
+    );
+
+    let mut days = Vec::new();
+    for offset in 0..total_days as usize {
+        let date = today + Duration::days(offset as i64);
+        let due = reviews.get(&date).copied().unwrap_or(0);
+        let introduced = if offset == 0 { introduced_today } else { 0 };
+        let new_cards = if offset < introduction_days {
+            // Spread what is left evenly, but use some spare capacity early
+            // on so more cards get reviewed before the exam.
+            let pace_remaining = remaining + introduced;
+            let even = pace_remaining.div_ceil(introduction_days - offset);
+            let spare = throughput.map_or(0, |t| t.saturating_sub(due));
+            let ahead = (spare / SPARE_CAPACITY_DIVISOR).min(even * MAX_AHEAD_PERCENT / 100);
+            remaining.min((even + ahead).saturating_sub(introduced))
+        } else {
+            0
+        };
+        remaining -= new_cards;
+        for _ in 0..new_cards {
+            project_reviews(template.clone(), date, last_day, &mut reviews);
+        }
+        days.push(PlanDay {
+            date,
+            new_cards: introduced + new_cards,
+            reviews: due,
+        });
+    }
+
+    Ok(StudyPlan {
+        deck: deck.to_string(),
+        exam_date,
+        throughput,
+        days,
+    })
+}
+
+fn introduced_today<'a>(
+    manager: &'a SpacedRepetitionManager,
+    deck: &str,
+    today: NaiveDate,
+) -> io::Result<HashSet<&'a str>> {
+    let start = local_midnight(today)?;
+    Ok(manager
+        .review_log
+        .iter()
+        .filter(|r| r.new_card && r.reviewed_at >= start)
+        .filter(|r| {
+            manager
+                .flashcards
+                .get(&r.question)
+                .is_some_and(|f| f.deck == deck)
+        })
+        .map(|r| r.question.as_str())
+        .collect())
+}
+
+/// How many new cards each deck with an accepted plan may still introduce
+/// today. The plan is regenerated from the current state every time, so
+/// falling behind or adding cards spreads the difference over the days left.
+pub fn new_cards_remaining(
+    manager: &SpacedRepetitionManager,
+) -> io::Result<HashMap<String, usize>> {
+    let today = Local::now().date_naive();
+    let mut remaining = HashMap::new();
+    for (deck, exam_date) in &manager.settings.deck_exams {
+        let exam_date = parse_date(exam_date)?;
+        let introduced = introduced_today(manager, deck, today)?;
+        let allowed = match generate(manager, deck, exam_date, today, introduced.len()) {
+            Ok(plan) => plan.days[0].new_cards,
+            // Past the exam the plan no longer limits anything.
+            Err(_) => continue,
+        };
+        remaining.insert(deck.clone(), allowed.saturating_sub(introduced.len()));
+    }
+    Ok(remaining)
+}
+
+fn to_markdown(plan: &StudyPlan) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Study plan: {}", plan.deck);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Exam: {}", plan.exam_date);
+    match plan.throughput {
+        Some(throughput) => {
+            let _ = writeln!(out, "Usual throughput: {} reviews per day", throughput);
+        }
+        None => {
+            let _ = writeln!(
+                out,
+                "Usual throughput: unknown (no reviews in the last 30 days)"
+            );
+        }
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| Date | New cards | Expected reviews |");
+    let _ = writeln!(out, "| --- | ---: | ---: |");
+    for day in &plan.days {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} |",
+            day.date, day.new_cards, day.reviews
+        );
+    }
+    out
+}
+
+fn to_ics(plan: &StudyPlan) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut out = String::new();
+    out.push_str("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//words//study plan//EN\r\n");
+    for day in plan.days.iter().filter(|d| d.new_cards + d.reviews > 0) {
+        let date = day.date.format("%Y%m%d");
+        let _ = write!(
+            out,
+            "BEGIN:VEVENT\r\nUID:words-plan-{}-{}\r\nDTSTAMP:{}\r\nDTSTART;VALUE=DATE:{}\r\nDTEND;VALUE=DATE:{}\r\nSUMMARY:{}: {} new cards, ~{} reviews\r\nEND:VEVENT\r\n",
+            plan.deck.replace(|c: char| !c.is_ascii_alphanumeric(), "-"),
+            date,
+            stamp,
+            date,
+            (day.date + Duration::days(1)).format("%Y%m%d"),
+            plan.deck.replace(['\\', ';', ','], " "),
+            day.new_cards,
+            day.reviews
+        );
+    }
+    out.push_str("END:VCALENDAR\r\n");
+    out
+}
+
+pub fn run_plan_command(manager: &mut SpacedRepetitionManager, args: &[String]) -> io::Result<()> {
+    let mut exam_date = None;
+    let mut deck = crate::default_deck();
+    let mut format = "markdown".to_string();
+    let mut output = None;
+    let mut accept = false;
+    let mut clear = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| usage_error("plan", &format!("{} expects a value", arg)))
+        };
+        match arg.as_str() {
+            "--exam-date" => exam_date = Some(parse_date(&value()?)?),
+            "--deck" => deck = value()?,
+            "--format" => format = value()?,
+            "--output" => output = Some(value()?),
+            "--accept" => accept = true,
+            "--clear" => clear = true,
+            _ => return Err(usage_error("plan", &format!("unknown option: {}", arg))),
+        }
+    }
+
+    if clear {
+        manager.settings.deck_exams.remove(&deck);
+        manager.save_settings()?;
+        println!("Removed the study plan for {}.", deck);
+        return Ok(());
+    }
+    let exam_date = exam_date.ok_or_else(|| usage_error("plan", "--exam-date is required"))?;
+    let today = Local::now().date_naive();
+    let introduced = introduced_today(manager, &deck, today)?;
+    let plan = generate(manager, &deck, exam_date, today, introduced.len())?;
+    let rendered = match format.as_str() {
+        "markdown" | "md" => to_markdown(&plan),
+        "ics" => to_ics(&plan),
+        _ => return Err(invalid(format!("unknown plan format: {}", format))),
+    };
+    match output {
+        Some(path) => fs::write(&path, rendered)?,
+        None => print!("{}", rendered),
+    }
+
+    if accept {
+        manager
+            .settings
+            .deck_exams
+            .insert(deck.clone(), exam_date.to_string());
+        manager.save_settings()?;
+        println!("Daily new cards in {} now follow this plan.", deck);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReviewRecord;
+
+    const NEW_CARDS: usize = 300;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()
+    }
+
+    /// A deck of new cards plus one reviewed card in `reviewed_deck` with
+    /// `reviews` log entries from yesterday.
+    fn manager(reviewed_deck: &str, reviews: usize) -> SpacedRepetitionManager {
+        let mut manager =
+            SpacedRepetitionManager::new(5, String::new(), String::new(), String::new());
+        for i in 0..NEW_CARDS {
+            manager.insert_flashcard(Flashcard::new(
+                format!("q{}", i),
+                String::new(),
+                String::new(),
+                "exam".to_string(),
+            ));
+        }
+        let mut reviewed = Flashcard::new(
+            "reviewed".to_string(),
+            String::new(),
+            String::new(),
+            reviewed_deck.to_string(),
+        );
+        // Not due again before the exam.
+        reviewed.next_review = local_midnight(today() + Duration::days(365)).unwrap();
+        reviewed.interval = 365;
+        manager.insert_flashcard(reviewed);
+        let yesterday = local_midnight(today() - Duration::days(1)).unwrap() + 3600;
+        manager.review_log = (0..reviews)
+            .map(|i| ReviewRecord {
+                question: "reviewed".to_string(),
+                reviewed_at: yesterday + i as u64,
+                performance: 4,
+                interval: 1,
+                confidence: None,
+                new_card: false,
+                typed_answer: None,
+                duration_secs: 10,
+            })
+            .collect();
+        manager
+    }
+
+    fn plan(manager: &SpacedRepetitionManager, days: i64) -> StudyPlan {
+        generate(
+            manager,
+            "exam",
+            today() + Duration::days(days),
+            today(),
+            0,
+        )
+        .unwrap()
+    }
+
+    fn new_cards(plan: &StudyPlan) -> Vec<usize> {
+        plan.days.iter().map(|d| d.new_cards).collect()
+    }
+
+    #[test]
+    fn without_history_cards_are_spread_evenly() {
+        let plan = plan(&manager("exam", 0), 60);
+        let new_cards = new_cards(&plan);
+        assert_eq!(plan.throughput, None);
+        assert_eq!(new_cards.iter().sum::<usize>(), NEW_CARDS);
+        // 53 introduction days, then a week of consolidation.
+        assert!(new_cards[..53].iter().all(|n| (5..=6).contains(n)));
+        assert!(new_cards[53..].iter().all(|n| *n == 0));
+    }
+
+    #[test]
+    fn spare_capacity_brings_cards_forward_only_a_little() {
+        let plan = plan(&manager("exam", 150), 60);
+        let new_cards = new_cards(&plan);
+        assert_eq!(plan.throughput, Some(150));
+        assert_eq!(new_cards.iter().sum::<usize>(), NEW_CARDS);
+        // At most 50% above the even pace of 6 cards a day...
+        assert!(new_cards.iter().all(|n| *n <= 9), "{:?}", new_cards);
+        assert!(new_cards[0] > 6);
+        // ...so introductions still go on for most of the plan.
+        let last = new_cards.iter().rposition(|n| *n > 0).unwrap();
+        assert!(last >= 30, "{:?}", new_cards);
+        assert!(new_cards[..=last].iter().all(|n| *n > 0), "{:?}", new_cards);
+        assert!(new_cards[53..].iter().all(|n| *n == 0));
+    }
+
+    #[test]
+    fn throughput_counts_only_the_planned_deck() {
+        let plan = plan(&manager("other", 150), 60);
+        assert_eq!(plan.throughput, None);
+        assert_eq!(plan.days[0].new_cards, 6);
+    }
+
+    #[test]
+    fn short_plans_still_introduce_every_card() {
+        let plan = plan(&manager("exam", 150), 10);
+        let new_cards = new_cards(&plan);
+        assert_eq!(new_cards.iter().sum::<usize>(), NEW_CARDS);
+        // Two days of consolidation for a ten-day plan.
+        assert_eq!(&new_cards[8..], &[0, 0]);
+    }
+
+    /// Introduces `count` of the new cards today: they were graded and are
+    /// due again tomorrow.
+    fn introduce(manager: &mut SpacedRepetitionManager, count: usize) -> usize {
+        let tomorrow = local_midnight(today() + Duration::days(1)).unwrap() + 3600;
+        for i in 0..count {
+            let flashcard = manager.flashcards.get_mut(&format!("q{}", i)).unwrap();
+            flashcard.update(ASSUMED_PERFORMANCE);
+            flashcard.next_review = tomorrow;
+        }
+        count
+    }
+
+    #[test]
+    fn cards_introduced_today_stay_on_today() {
+        let mut manager = manager("exam", 0);
+        manager.flashcards.retain(|q, _| ["q0", "q1", "q2", "q3"].contains(&q.as_str()));
+        let introduced = introduce(&mut manager, 4);
+        let plan = generate(&manager, "exam", today() + Duration::days(4), today(), introduced)
+            .unwrap();
+        assert_eq!(new_cards(&plan), [4, 0, 0, 0]);
+        // Their first reviews are projected from their actual next review.
+        assert_eq!(plan.days[1].reviews, 4);
+    }
+
+    #[test]
+    fn cards_introduced_today_count_towards_the_pace() {
+        let before = plan(&manager("exam", 0), 60);
+        let mut manager = manager("exam", 0);
+        let introduced = introduce(&mut manager, 4);
+        let after = generate(&manager, "exam", today() + Duration::days(60), today(), introduced)
+            .unwrap();
+        assert_eq!(after.days[0].new_cards, before.days[0].new_cards);
+        assert_eq!(new_cards(&after).iter().sum::<usize>(), NEW_CARDS);
+
+        // Beyond today's pace, nothing more is planned for today.
+        let introduced = introduce(&mut manager, 20);
+        let after = generate(&manager, "exam", today() + Duration::days(60), today(), introduced)
+            .unwrap();
+        assert_eq!(after.days[0].new_cards, 20);
+        assert_eq!(new_cards(&after).iter().sum::<usize>(), NEW_CARDS);
+    }
+
+    #[test]
+    fn exam_must_be_in_the_future() {
+        let manager = manager("exam", 0);
+        for days in [0, -1] {
+            assert!(generate(
+                &manager,
+                "exam",
+                today() + Duration::days(days),
+                today(),
+                0
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,609 @@
+use crate::{now_secs, read_json_or_default, start_of_today, usage_error, SpacedRepetitionManager};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+/// How long a client may take to send its request, and to accept each write
+/// of the response. The server handles one connection at a time, so a client
+/// that stalls holds up everyone else until then.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound on the request line plus headers.
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+
+/// An API token as stored in the settings. Only a hash of the secret is
+/// kept; the token itself is shown once when it is created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    id: String,
+    hash: String,
+    read_only: bool,
+    requests_per_minute: u32,
+    created_at: u64,
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Per-token token bucket, refilled continuously at the token's rate.
+struct RateLimiter {
+    buckets: HashMap<String, (f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new() -> Self {
+        RateLimiter {
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes one request from the token's bucket, or returns how many
+    /// seconds to wait before the next request is allowed.
+    fn check(&mut self, token: &ApiToken) -> Result<(), u64> {
+        let capacity = token.requests_per_minute.max(1) as f64;
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+        let (available, updated) = self
+            .buckets
+            .entry(token.id.clone())
+            .or_insert((capacity, now));
+        *available =
+            (*available + now.duration_since(*updated).as_secs_f64() * per_second).min(capacity);
+        *updated = now;
+        if *available >= 1.0 {
+            *available -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - *available) / per_second).ceil() as u64)
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+}
+
+impl Request {
+    /// The credentials of a `Bearer` authorization header. The scheme name
+    /// is case-insensitive.
+    fn bearer_token(&self) -> Option<&str> {
+        let (scheme, token) = self.headers.get("authorization")?.split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim())
+            .filter(|token| !token.is_empty())
+    }
+
+    fn is_read(&self) -> bool {
+        self.method == "GET" || self.method == "HEAD"
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Reads from a stream until a fixed deadline, however slowly the bytes
+/// trickle in.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request not received in time",
+            ));
+        }
+        let mut stream = self.stream;
+        stream.set_read_timeout(Some(left))?;
+        stream.read(buf)
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let deadline = Deadline {
+        stream,
+        until: Instant::now() + REQUEST_TIMEOUT,
+    };
+    let mut reader = BufReader::new(deadline.take(MAX_HEAD_BYTES));
+    let incomplete = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request head incomplete or too large",
+        )
+    };
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed request line",
+            ))
+        }
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+            return Err(incomplete());
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(Request {
+        method,
+        path,
+        headers,
+    })
+}
+
+/// Writes the response; for HEAD requests the body is left out but its
+/// length is still reported.
+fn write_response(mut stream: &TcpStream, response: &Response, head_only: bool) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    let body = if head_only { "" } else { &response.body };
+    write!(stream, "{}\r\n{}", head, body)?;
+    stream.flush()
+}
+
+/// Reloads the deck from disk so the numbers reflect changes made by other
+/// `words` processes, timing the load as the storage latency.
+fn metrics(manager: &mut SpacedRepetitionManager) -> io::Result<Response> {
+    let started = Instant::now();
+    manager.flashcards.clear();
+    manager.load()?;
+    let load_seconds = started.elapsed().as_secs_f64();
+
+    let now = now_secs()?;
+    let today = start_of_today()?;
+    let mut decks: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for flashcard in manager.flashcards.values() {
+        let (total, due) = decks.entry(&flashcard.deck).or_default();
+        *total += 1;
+        if !flashcard.draft && flashcard.next_review <= now {
+            *due += 1;
+        }
+    }
+    let reviews_today = manager
+        .review_log
+        .iter()
+        .filter(|r| r.reviewed_at >= today)
+        .count();
+
+    let mut body = String::new();
+    let cards: Vec<_> = decks
+        .iter()
+        .map(|(deck, (total, _))| (Some(*deck), *total as f64))
+        .collect();
+    let due: Vec<_> = decks
+        .iter()
+        .map(|(deck, (_, due))| (Some(*deck), *due as f64))
+        .collect();
+    write_gauge(
+        &mut body,
+        "words_cards",
+        "Number of cards per deck.",
+        &cards,
+    );
+    write_gauge(
+        &mut body,
+        "words_cards_due",
+        "Number of cards due for review per deck.",
+        &due,
+    );
+    write_gauge(
+        &mut body,
+        "words_reviews_today",
+        "Reviews recorded since local midnight.",
+        &[(None, reviews_today as f64)],
+    );
+    write_gauge(
+        &mut body,
+        "words_storage_load_seconds",
+        "Time taken to load the deck from disk.",
+        &[(None, load_seconds)],
+    );
+
+    Ok(Response {
+        status: "200 OK",
+        content_type: "text/plain; version=0.0.4; charset=utf-8",
+        headers: Vec::new(),
+        body,
+    })
+}
+
+/// Appends a gauge in the Prometheus text format, with an optional `deck`
+/// label per sample.
+fn write_gauge(body: &mut String, name: &str, help: &str, samples: &[(Option<&str>, f64)]) {
+    let _ = writeln!(body, "# HELP {} {}", name, help);
+    let _ = writeln!(body, "# TYPE {} gauge", name);
+    for (deck, value) in samples {
+        match deck {
+            Some(deck) => {
+                let _ = writeln!(
+                    body,
+                    "{}{{deck=\"{}\"}} {}",
+                    name,
+                    escape_label(deck),
+                    value
+                );
+            }
+            None => {
+                let _ = writeln!(body, "{} {}", name, value);
+            }
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Checks the bearer token against the configured tokens. Every endpoint
+/// except `/healthz` needs a token once any exist, or always when the server
+/// listens beyond localhost; read-only tokens are limited to GET and HEAD
+/// requests. Beyond localhost, requests without a token are turned away
+/// before the tokens are re-read from disk.
+fn authorize(
+    manager: &mut SpacedRepetitionManager,
+    limiter: &mut RateLimiter,
+    public: bool,
+    request: &Request,
+) -> Result<(), Response> {
+    if request.path == "/healthz" {
+        return Ok(());
+    }
+    let unauthorized = || {
+        Response::text("401 Unauthorized", "missing or invalid token\n")
+            .with_header("WWW-Authenticate", "Bearer")
+    };
+    let bearer_token = request.bearer_token();
+    if bearer_token.is_none() && public {
+        return Err(unauthorized());
+    }
+    // Re-read the tokens so that created or revoked tokens apply without a
+    // restart.
+    let settings = read_json_or_default(&manager.settings_file)
+        .map_err(|e| Response::text("500 Internal Server Error", format!("{}\n", e)))?;
+    manager.settings = settings;
+    if manager.settings.api_tokens.is_empty() && !public {
+        return Ok(());
+    }
+
+    let hash = hash_token(bearer_token.ok_or_else(unauthorized)?);
+    let token = manager
+        .settings
+        .api_tokens
+        .iter()
+        .find(|t| t.hash == hash)
+        .ok_or_else(unauthorized)?;
+    if token.read_only && !request.is_read() {
+        return Err(Response::text("403 Forbidden", "token is read-only\n"));
+    }
+    limiter.check(token).map_err(|retry_after| {
+        Response::text("429 Too Many Requests", "rate limit exceeded\n")
+            .with_header("Retry-After", retry_after.to_string())
+    })
+}
+
+fn handle(
+    manager: &mut SpacedRepetitionManager,
+    limiter: &mut RateLimiter,
+    public: bool,
+    request: &Request,
+) -> Response {
+    if let Err(response) = authorize(manager, limiter, public, request) {
+        return response;
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET" | "HEAD", "/healthz") => Response::text("200 OK", "ok\n"),
+        ("GET" | "HEAD", "/metrics") => metrics(manager)
+            .unwrap_or_else(|e| Response::text("500 Internal Server Error", format!("{}\n", e))),
+        (_, "/healthz") | (_, "/metrics") => {
+            Response::text("405 Method Not Allowed", "method not allowed\n")
+                .with_header("Allow", "GET, HEAD")
+        }
+        _ => Response::text("404 Not Found", "not found\n"),
+    }
+}
+
+pub fn serve(manager: &mut SpacedRepetitionManager, addr: &str) -> io::Result<()> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    let public = addrs.iter().any(|a| !a.ip().is_loopback());
+    if public && manager.settings.api_tokens.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "refusing to listen beyond localhost without API tokens; create one with `words token create`",
+        ));
+    }
+    let listener = TcpListener::bind(&addrs[..])?;
+    let mut limiter = RateLimiter::new();
+    println!("Listening on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Connection failed: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream.set_write_timeout(Some(REQUEST_TIMEOUT)) {
+            eprintln!("Connection failed: {}", e);
+            continue;
+        }
+        let result = read_request(&stream).and_then(|request| {
+            let response = handle(manager, &mut limiter, public, &request);
+            write_response(&stream, &response, request.method == "HEAD")
+        });
+        if let Err(e) = result {
+            eprintln!("Request failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+pub fn run_token_command(manager: &mut SpacedRepetitionManager, args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("create") => {
+            let mut read_only = false;
+            let mut requests_per_minute = DEFAULT_REQUESTS_PER_MINUTE;
+            let mut options = args[1..].iter();
+            while let Some(option) = options.next() {
+                match option.as_str() {
+                    "--read-only" => read_only = true,
+                    "--rate" => {
+                        requests_per_minute = options
+                            .next()
+                            .and_then(|rate| rate.parse().ok())
+                            .filter(|rate| *rate > 0)
+                            .ok_or_else(|| {
+                                usage_error("token", "--rate expects a number of requests per minute")
+                            })?;
+                    }
+                    _ => return Err(usage_error("token", &format!("unknown option: {}", option))),
+                }
+            }
+
+            let mut secret = [0u8; 32];
+            getrandom::fill(&mut secret).map_err(|e| io::Error::other(e.to_string()))?;
+            let token: String = secret.iter().map(|b| format!("{:02x}", b)).collect();
+            let hash = hash_token(&token);
+            let id = hash[..8].to_string();
+            manager.settings.api_tokens.push(ApiToken {
+                id: id.clone(),
+                hash,
+                read_only,
+                requests_per_minute,
+                created_at: now_secs()?,
+            });
+            manager.save_settings()?;
+            println!("Created token {}. It is shown only once:", id);
+            println!("{}", token);
+        }
+        Some("list") => {
+            for token in &manager.settings.api_tokens {
+                println!(
+                    "{}  {}  {} requests/minute",
+                    token.id,
+                    if token.read_only {
+                        "read-only"
+                    } else {
+                        "read-write"
+                    },
+                    token.requests_per_minute
+                );
+            }
+        }
+        Some("revoke") if args.len() == 2 => {
+            let before = manager.settings.api_tokens.len();
+            manager.settings.api_tokens.retain(|t| t.id != args[1]);
+            if manager.settings.api_tokens.len() == before {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no token with id {}", args[1]),
+                ));
+            }
+            manager.save_settings()?;
+            println!("Revoked token {}", args[1]);
+        }
+        _ => return Err(usage_error("token", "invalid token command")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn token(id: &str, read_only: bool, requests_per_minute: u32) -> ApiToken {
+        ApiToken {
+            id: id.to_string(),
+            hash: hash_token(&format!("secret-{}", id)),
+            read_only,
+            requests_per_minute,
+            created_at: 0,
+        }
+    }
+
+    fn request(method: &str, path: &str, authorization: Option<&str>) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: authorization
+                .map(|value| ("authorization".to_string(), value.to_string()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// A manager whose settings file holds `tokens`.
+    fn manager(name: &str, tokens: Vec<ApiToken>) -> (SpacedRepetitionManager, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "words-server-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let mut manager = SpacedRepetitionManager::new(
+            5,
+            String::new(),
+            String::new(),
+            path.to_string_lossy().into_owned(),
+        );
+        manager.settings.api_tokens = tokens;
+        manager.save_settings().unwrap();
+        (manager, path)
+    }
+
+    fn status(result: Result<(), Response>) -> &'static str {
+        match result {
+            Ok(()) => "ok",
+            Err(response) => response.status,
+        }
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst_of_one_minute() {
+        let mut limiter = RateLimiter::new();
+        let slow = token("slow", false, 2);
+        assert_eq!(limiter.check(&slow), Ok(()));
+        assert_eq!(limiter.check(&slow), Ok(()));
+        let retry_after = limiter.check(&slow).unwrap_err();
+        assert!((29..=30).contains(&retry_after), "{}", retry_after);
+        // Buckets are per token.
+        assert_eq!(limiter.check(&token("other", false, 2)), Ok(()));
+    }
+
+    #[test]
+    fn bearer_scheme_is_case_insensitive() {
+        for value in ["Bearer abc", "bearer abc", "BEARER  abc "] {
+            assert_eq!(request("GET", "/", Some(value)).bearer_token(), Some("abc"));
+        }
+        assert_eq!(request("GET", "/", Some("Basic abc")).bearer_token(), None);
+        assert_eq!(request("GET", "/", Some("Bearer")).bearer_token(), None);
+        assert_eq!(request("GET", "/", None).bearer_token(), None);
+    }
+
+    #[test]
+    fn tokens_are_checked_once_any_exist() {
+        let (mut manager, path) = manager(
+            "tokens",
+            vec![token("rw", false, 60), token("ro", true, 60)],
+        );
+        let mut limiter = RateLimiter::new();
+        let mut check = |method: &str, path: &str, authorization: Option<&str>| {
+            status(authorize(
+                &mut manager,
+                &mut limiter,
+                false,
+                &request(method, path, authorization),
+            ))
+        };
+        assert_eq!(check("GET", "/healthz", None), "ok");
+        assert_eq!(check("GET", "/metrics", None), "401 Unauthorized");
+        assert_eq!(check("GET", "/metrics", Some("Bearer wrong")), "401 Unauthorized");
+        assert_eq!(check("GET", "/metrics", Some("Bearer secret-rw")), "ok");
+        assert_eq!(check("POST", "/metrics", Some("Bearer secret-rw")), "ok");
+        assert_eq!(check("HEAD", "/metrics", Some("bearer secret-ro")), "ok");
+        assert_eq!(check("POST", "/metrics", Some("Bearer secret-ro")), "403 Forbidden");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn localhost_reopens_once_the_last_token_is_revoked() {
+        let (mut manager, path) = manager("revoke", vec![token("rw", false, 60)]);
+        let mut limiter = RateLimiter::new();
+        let metrics = request("GET", "/metrics", None);
+        assert_eq!(
+            status(authorize(&mut manager, &mut limiter, false, &metrics)),
+            "401 Unauthorized"
+        );
+        manager.settings.api_tokens.clear();
+        manager.save_settings().unwrap();
+        manager.settings.api_tokens.push(token("stale", false, 60));
+        assert_eq!(status(authorize(&mut manager, &mut limiter, false, &metrics)), "ok");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn public_server_always_needs_a_token() {
+        let (mut manager, path) = manager("public", Vec::new());
+        let mut limiter = RateLimiter::new();
+        let metrics = request("GET", "/metrics", None);
+        assert_eq!(
+            status(authorize(&mut manager, &mut limiter, true, &metrics)),
+            "401 Unauthorized"
+        );
+        let authorized = request("GET", "/metrics", Some("Bearer secret-rw"));
+        assert_eq!(
+            status(authorize(&mut manager, &mut limiter, true, &authorized)),
+            "401 Unauthorized"
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn rate_limit_applies_per_token() {
+        let (mut manager, path) = manager("rate", vec![token("rw", false, 1)]);
+        let mut limiter = RateLimiter::new();
+        let metrics = request("GET", "/metrics", Some("Bearer secret-rw"));
+        assert_eq!(status(authorize(&mut manager, &mut limiter, false, &metrics)), "ok");
+        let response = authorize(&mut manager, &mut limiter, false, &metrics).unwrap_err();
+        assert_eq!(response.status, "429 Too Many Requests");
+        assert!(response.headers.iter().any(|(name, _)| *name == "Retry-After"));
+        let _ = fs::remove_file(path);
+    }
+}
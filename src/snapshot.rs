@@ -0,0 +1,91 @@
+use crate::{now_secs, read_json_or_default, Flashcard};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u32,
+    pub name: String,
+    pub created_at: u64,
+    cards: Vec<String>,
+}
+
+/// Named point-in-time copies of the deck. Each card is stored once as a
+/// content-addressed object under `objects/`, so snapshots of a mostly
+/// unchanged deck only cost an index entry.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: &str) -> Self {
+        SnapshotStore {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    fn index_file(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    fn object_file(&self, hash: &str) -> PathBuf {
+        self.dir.join("objects").join(format!("{}.json", hash))
+    }
+
+    pub fn list(&self) -> io::Result<Vec<Snapshot>> {
+        read_json_or_default(self.index_file())
+    }
+
+    pub fn create(&self, name: &str, flashcards: &HashMap<String, Flashcard>) -> io::Result<u32> {
+        fs::create_dir_all(self.dir.join("objects"))?;
+
+        let mut questions: Vec<&String> = flashcards.keys().collect();
+        questions.sort();
+        let mut cards = Vec::with_capacity(questions.len());
+        for question in questions {
+            let data = serde_json::to_string(&flashcards[question])?;
+            let hash = format!("{:x}", Sha256::digest(data.as_bytes()));
+            let object_file = self.object_file(&hash);
+            if !object_file.exists() {
+                fs::write(object_file, data)?;
+            }
+            cards.push(hash);
+        }
+
+        let mut snapshots = self.list()?;
+        let id = snapshots.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+        snapshots.push(Snapshot {
+            id,
+            name: name.to_string(),
+            created_at: now_secs()?,
+            cards,
+        });
+        fs::write(self.index_file(), serde_json::to_string(&snapshots)?)?;
+        Ok(id)
+    }
+
+    pub fn restore(&self, id: u32) -> io::Result<Vec<Flashcard>> {
+        let snapshot = self
+            .list()?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no snapshot with id {}", id),
+                )
+            })?;
+        snapshot
+            .cards
+            .iter()
+            .map(|hash| {
+                let data = fs::read_to_string(self.object_file(hash))?;
+                Ok(serde_json::from_str(&data)?)
+            })
+            .collect()
+    }
+}
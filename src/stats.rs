@@ -0,0 +1,137 @@
+use crate::{local_midnight, Flashcard, ReviewRecord, PASSING_PERFORMANCE};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::io;
+
+const MATURE_INTERVAL: u32 = 21;
+const RETENTION_WINDOW: u64 = 30 * 86400;
+
+/// The state of the collection at local midnight of a given date,
+/// reconstructed from card creation times and the review log.
+struct DeckStats {
+    cards: usize,
+    mature: usize,
+    retention: Option<f64>,
+    reviews: usize,
+    seconds_studied: u64,
+}
+
+impl DeckStats {
+    fn at(flashcards: &HashMap<String, Flashcard>, review_log: &[ReviewRecord], time: u64) -> Self {
+        let mut intervals: HashMap<&str, u32> = HashMap::new();
+        let mut recent = 0;
+        let mut recent_passed = 0;
+        let mut reviews = 0;
+        let mut seconds_studied = 0;
+        for record in review_log.iter().filter(|r| r.reviewed_at < time) {
+            intervals.insert(&record.question, record.interval);
+            reviews += 1;
+            seconds_studied += record.duration_secs;
+            if record.reviewed_at + RETENTION_WINDOW >= time {
+                recent += 1;
+                if record.performance >= PASSING_PERFORMANCE {
+                    recent_passed += 1;
+                }
+            }
+        }
+
+        DeckStats {
+            cards: flashcards.values().filter(|f| f.created_at < time).count(),
+            mature: intervals.values().filter(|i| **i >= MATURE_INTERVAL).count(),
+            retention: (recent > 0).then(|| recent_passed as f64 * 100.0 / recent as f64),
+            reviews,
+            seconds_studied,
+        }
+    }
+}
+
+fn format_duration(seconds: u64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+}
+
+fn format_retention(retention: Option<f64>) -> String {
+    retention.map_or("-".to_string(), |r| format!("{:.0}%", r))
+}
+
+fn parse_date(date: &str) -> io::Result<(NaiveDate, u64)> {
+    let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected a date like 2024-01-01, got '{}'", date),
+        )
+    })?;
+    Ok((parsed, local_midnight(parsed)?))
+}
+
+/// Prints the collection as of two dates side by side with the change
+/// between them.
+pub fn print_comparison(
+    flashcards: &HashMap<String, Flashcard>,
+    review_log: &[ReviewRecord],
+    from: &str,
+    to: &str,
+) -> io::Result<()> {
+    let (from_date, from_time) = parse_date(from)?;
+    let (to_date, to_time) = parse_date(to)?;
+    if from_date > to_date {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is after {}; give the earlier date first", from_date, to_date),
+        ));
+    }
+    let before = DeckStats::at(flashcards, review_log, from_time);
+    let after = DeckStats::at(flashcards, review_log, to_time);
+
+    let delta = |a: usize, b: usize| format!("{:+}", b as i64 - a as i64);
+    let retention_change = match (before.retention, after.retention) {
+        (Some(a), Some(b)) => format!("{:+.0} pp", b - a),
+        _ => "-".to_string(),
+    };
+    let rows = [
+        (
+            "Cards",
+            before.cards.to_string(),
+            after.cards.to_string(),
+            delta(before.cards, after.cards),
+        ),
+        (
+            "Mature cards",
+            before.mature.to_string(),
+            after.mature.to_string(),
+            delta(before.mature, after.mature),
+        ),
+        (
+            "Retention (30 days)",
+            format_retention(before.retention),
+            format_retention(after.retention),
+            retention_change,
+        ),
+        (
+            "Reviews",
+            before.reviews.to_string(),
+            after.reviews.to_string(),
+            delta(before.reviews, after.reviews),
+        ),
+        (
+            "Time studied",
+            format_duration(before.seconds_studied),
+            format_duration(after.seconds_studied),
+            format!(
+                "+{}",
+                format_duration(after.seconds_studied.saturating_sub(before.seconds_studied))
+            ),
+        ),
+    ];
+
+    println!(
+        "{:<20} {:>12} {:>12} {:>12}",
+        "",
+        from_date.to_string(),
+        to_date.to_string(),
+        "change"
+    );
+    for (label, a, b, change) in rows {
+        println!("{:<20} {:>12} {:>12} {:>12}", label, a, b, change);
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use chrono::{DateTime, Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A recurring time window in which cards may be shown, e.g. `mon-fri 9-18`.
+/// Days are numbered from Monday (0) and hours are local time, with the end
+/// hour exclusive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawStudyWindow")]
+pub struct StudyWindow {
+    days: Vec<u32>,
+    start_hour: u32,
+    end_hour: u32,
+}
+
+/// A window as stored in the settings, checked before it is used since the
+/// file may have been edited by hand.
+#[derive(Deserialize)]
+struct RawStudyWindow {
+    days: Vec<u32>,
+    start_hour: u32,
+    end_hour: u32,
+}
+
+impl TryFrom<RawStudyWindow> for StudyWindow {
+    type Error = String;
+
+    fn try_from(raw: RawStudyWindow) -> Result<Self, String> {
+        if let Some(day) = raw.days.iter().find(|d| **d as usize >= DAY_NAMES.len()) {
+            return Err(format!("invalid day number {} in study window", day));
+        }
+        if raw.start_hour >= raw.end_hour || raw.end_hour > 24 {
+            return Err(format!(
+                "invalid hour range {}-{} in study window",
+                raw.start_hour, raw.end_hour
+            ));
+        }
+        Ok(StudyWindow {
+            days: raw.days,
+            start_hour: raw.start_hour,
+            end_hour: raw.end_hour,
+        })
+    }
+}
+
+impl StudyWindow {
+    fn contains(&self, time: &DateTime<Local>) -> bool {
+        self.days.contains(&time.weekday().num_days_from_monday())
+            && (self.start_hour..self.end_hour).contains(&time.hour())
+    }
+
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (days, hours) = match spec.split_once(' ') {
+            Some((days, hours)) => (days, hours.trim()),
+            None => ("daily", spec),
+        };
+        let (start, end) = hours
+            .split_once('-')
+            .ok_or_else(|| format!("expected hours like 9-18, got '{}'", hours))?;
+        let start_hour: u32 = start.parse().map_err(|_| format!("invalid hour '{}'", start))?;
+        let end_hour: u32 = end.parse().map_err(|_| format!("invalid hour '{}'", end))?;
+        if start_hour >= end_hour || end_hour > 24 {
+            return Err(format!("invalid hour range '{}'", hours));
+        }
+        Ok(StudyWindow {
+            days: parse_days(days)?,
+            start_hour,
+            end_hour,
+        })
+    }
+}
+
+fn parse_day(name: &str) -> Result<u32, String> {
+    DAY_NAMES
+        .iter()
+        .position(|d| *d == name)
+        .map(|d| d as u32)
+        .ok_or_else(|| format!("unknown day '{}'", name))
+}
+
+fn parse_days(spec: &str) -> Result<Vec<u32>, String> {
+    let spec = spec.to_lowercase();
+    if spec == "daily" {
+        return Ok((0..7).collect());
+    }
+    let mut days = Vec::new();
+    for part in spec.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_day(first)?, parse_day(last)?);
+                if first > last {
+                    return Err(format!("invalid day range '{}'", part));
+                }
+                days.extend(first..=last);
+            }
+            None => days.push(parse_day(part)?),
+        }
+    }
+    days.sort();
+    days.dedup();
+    Ok(days)
+}
+
+impl fmt::Display for StudyWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days: Vec<&str> = self.days.iter().map(|d| DAY_NAMES[*d as usize]).collect();
+        write!(f, "{} {}-{}", days.join(","), self.start_hour, self.end_hour)
+    }
+}
+
+/// Parses a `;`-separated list of windows such as `mon-fri 9-18; sat 10-12`.
+pub fn parse_windows(spec: &str) -> Result<Vec<StudyWindow>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(StudyWindow::parse)
+        .collect()
+}
+
+pub fn format_windows(windows: &[StudyWindow]) -> String {
+    if windows.is_empty() {
+        return "any time".to_string();
+    }
+    let windows: Vec<String> = windows.iter().map(|w| w.to_string()).collect();
+    windows.join("; ")
+}
+
+/// An empty list places no restriction.
+pub fn is_open(windows: &[StudyWindow], time: &DateTime<Local>) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.contains(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(days: &[u32], start_hour: u32, end_hour: u32) -> StudyWindow {
+        StudyWindow {
+            days: days.to_vec(),
+            start_hour,
+            end_hour,
+        }
+    }
+
+    #[test]
+    fn daily_and_bare_hours_cover_every_day() {
+        let every_day = window(&[0, 1, 2, 3, 4, 5, 6], 9, 17);
+        assert_eq!(StudyWindow::parse("daily 9-17"), Ok(every_day.clone()));
+        assert_eq!(StudyWindow::parse("9-17"), Ok(every_day));
+        assert!(StudyWindow::parse("daily").is_err());
+    }
+
+    #[test]
+    fn day_lists_and_ranges() {
+        assert_eq!(
+            StudyWindow::parse("mon-wed,sat 9-18"),
+            Ok(window(&[0, 1, 2, 5], 9, 18))
+        );
+        assert_eq!(
+            StudyWindow::parse("sun,mon,sun 9-18"),
+            Ok(window(&[0, 6], 9, 18))
+        );
+        assert!(StudyWindow::parse("fri-mon 9-18").is_err());
+        assert!(StudyWindow::parse("monday 9-18").is_err());
+    }
+
+    #[test]
+    fn day_names_are_case_insensitive() {
+        assert_eq!(
+            StudyWindow::parse("Mon-FRI 9-18"),
+            Ok(window(&[0, 1, 2, 3, 4], 9, 18))
+        );
+        assert!(StudyWindow::parse("DAILY 9-18").is_ok());
+    }
+
+    #[test]
+    fn hour_ranges() {
+        assert_eq!(StudyWindow::parse("0-24"), Ok(window(&[0, 1, 2, 3, 4, 5, 6], 0, 24)));
+        assert!(StudyWindow::parse("9-9").is_err());
+        assert!(StudyWindow::parse("18-9").is_err());
+        assert!(StudyWindow::parse("0-25").is_err());
+        assert!(StudyWindow::parse("9").is_err());
+        assert!(StudyWindow::parse("mon 9-x").is_err());
+    }
+
+    #[test]
+    fn window_lists_are_separated_by_semicolons() {
+        assert_eq!(
+            parse_windows(" mon-fri 9-18; ;sat,sun 10-12 "),
+            Ok(vec![window(&[0, 1, 2, 3, 4], 9, 18), window(&[5, 6], 10, 12)])
+        );
+        assert_eq!(parse_windows(""), Ok(Vec::new()));
+        assert!(parse_windows("mon 9-18; bad").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let windows = parse_windows("mon-fri 9-18; sun 0-24").unwrap();
+        assert_eq!(format_windows(&windows), "mon,tue,wed,thu,fri 9-18; sun 0-24");
+        assert_eq!(parse_windows(&format_windows(&windows)), Ok(windows));
+    }
+
+    #[test]
+    fn invalid_stored_windows_are_rejected() {
+        let valid = r#"{"days":[0,6],"start_hour":9,"end_hour":18}"#;
+        assert_eq!(
+            serde_json::from_str::<StudyWindow>(valid).unwrap(),
+            window(&[0, 6], 9, 18)
+        );
+        for invalid in [
+            r#"{"days":[7],"start_hour":9,"end_hour":18}"#,
+            r#"{"days":[0],"start_hour":18,"end_hour":9}"#,
+            r#"{"days":[0],"start_hour":0,"end_hour":25}"#,
+        ] {
+            assert!(serde_json::from_str::<StudyWindow>(invalid).is_err(), "{}", invalid);
+        }
+    }
+}